@@ -0,0 +1,47 @@
+// Copyright (C) 2023, Alex Badics
+// This file is part of ar-drivers-rs
+// Licensed under the MIT license. See LICENSE file in the project root for details.
+
+use std::time::{Duration, Instant};
+
+use ar_drivers::{any_glasses, ARGlassesExt, Error};
+use clap::Parser;
+
+/// Print the events of the AR glasses as newline delimited JSON, one event per line.
+/// Stop with Ctrl-C, or use --duration.
+#[derive(clap::Parser, Debug)]
+struct CliArgs {
+    /// Stop after this many seconds
+    #[clap(long, short, value_parser = parse_duration)]
+    duration: Option<Duration>,
+}
+
+fn parse_duration(arg: &str) -> Result<Duration, String> {
+    let seconds: f64 = arg.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| "must be a non-negative number of seconds".into())
+}
+
+fn main() {
+    let args = CliArgs::parse();
+    let mut glasses = any_glasses().unwrap();
+    eprintln!("Got glasses, serial={}", glasses.serial().unwrap());
+
+    let start = Instant::now();
+    for event in glasses.events() {
+        if args
+            .duration
+            .is_some_and(|duration| start.elapsed() > duration)
+        {
+            break;
+        }
+        match event {
+            Ok(event) => println!("{}", serde_json::to_string(&event).unwrap()),
+            Err(Error::Timeout | Error::PacketTimeout) => continue,
+            Err(e) => {
+                eprintln!("Error: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
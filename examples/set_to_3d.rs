@@ -44,10 +44,10 @@ fn main() {
     let args = CliArgs::parse();
     let mut glasses = any_glasses().unwrap();
     println!("Got glasses, serial={}", glasses.serial().unwrap());
-    println!(
-        "Display mode was: {:?}",
-        glasses.get_display_mode().unwrap()
-    );
+    match glasses.get_display_mode() {
+        Ok(display_mode) => println!("Display mode was: {display_mode:?}"),
+        Err(e) => println!("Could not get the display mode: {e}"),
+    }
 
     glasses
         .set_display_mode(match args.mode {
@@ -0,0 +1,347 @@
+// Copyright (C) 2023, Alex Badics
+// This file is part of ar-drivers-rs
+// Licensed under the MIT license. See LICENSE file in the project root for details.
+
+//! Enumeration of connected AR glasses, without connecting to them. See [`list_devices`]
+//! and [`register_hotplug`]. For debugging, see [`dump_usb_topology`].
+//! It only uses [`rusb`] for communication.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use rusb::{Device, DeviceList, GlobalContext, Hotplug, HotplugBuilder, UsbContext};
+
+use crate::{Error, Result};
+
+/// Describes a connected pair of supported glasses. Returned by [`list_devices`].
+#[derive(Debug, Clone)]
+pub struct GlassesInfo {
+    /// Number of the USB bus the glasses are connected to
+    pub bus_number: u8,
+    /// Address of the glasses on the USB bus
+    pub address: u8,
+    /// USB Vendor ID
+    pub vendor_id: u16,
+    /// USB Product ID
+    pub product_id: u16,
+    /// USB product string. `None` if it could not be read (e.g. because of missing permissions)
+    pub product_string: Option<String>,
+    /// Serial number. For Rokid glasses it is the same as what [`crate::ARGlasses::serial`]
+    /// returns, for others it is the USB serial number string. `None` if it could not be read.
+    pub serial: Option<String>,
+    /// Name of the detected model. Same as what [`crate::ARGlasses::name`] returns, except
+    /// for hotplug events (see [`register_hotplug`])
+    pub model: &'static str,
+}
+
+/// List all connected glasses that are supported by the enabled features.
+///
+/// The devices are only opened to read their product strings, no interfaces are claimed,
+/// so this can be called while the glasses are in use.
+pub fn list_devices() -> Result<Vec<GlassesInfo>> {
+    let mut result = Vec::new();
+    for device in DeviceList::new()?.iter() {
+        let Ok(desc) = device.device_descriptor() else {
+            continue;
+        };
+        let Some(model) = known_model(desc.vendor_id(), desc.product_id()) else {
+            continue;
+        };
+        let handle = device.open().ok();
+        let product_string = handle
+            .as_ref()
+            .and_then(|handle| handle.read_product_string_ascii(&desc).ok());
+        #[allow(unused_mut)]
+        let mut serial = handle
+            .as_ref()
+            .and_then(|handle| handle.read_serial_number_string_ascii(&desc).ok());
+        #[allow(unused_mut)]
+        let mut model = model;
+        #[cfg(feature = "rokid")]
+        if desc.vendor_id() == crate::rokid::RokidAir::VID {
+            model = crate::rokid::RokidModel::from_product_string(
+                product_string.as_deref().unwrap_or_default(),
+            )
+            .name();
+            serial = handle.as_ref().and_then(|handle| {
+                crate::rokid::RokidAir::read_serial(handle, crate::rokid::RokidAir::DEFAULT_TIMEOUT)
+                    .ok()
+            });
+        }
+        result.push(GlassesInfo {
+            bus_number: device.bus_number(),
+            address: device.address(),
+            vendor_id: desc.vendor_id(),
+            product_id: desc.product_id(),
+            product_string,
+            serial,
+            model,
+        });
+    }
+    Ok(result)
+}
+
+/// Describe the USB configurations, interfaces and endpoints of all connected glasses
+/// that are supported by the enabled features, in a human readable form.
+///
+/// Only the descriptors are read, the devices are not opened, so this works even if the
+/// glasses are in use or can't be claimed. Useful for bug reports about unexpected
+/// USB structures. Returns [`Error::NotFound`] if no supported glasses are connected.
+pub fn dump_usb_topology() -> Result<String> {
+    let mut lines = Vec::new();
+    for device in DeviceList::new()?.iter() {
+        let Ok(desc) = device.device_descriptor() else {
+            continue;
+        };
+        let Some(model) = known_model(desc.vendor_id(), desc.product_id()) else {
+            continue;
+        };
+        lines.push(format!(
+            "{model} ({:04x}:{:04x}) on bus {} address {}",
+            desc.vendor_id(),
+            desc.product_id(),
+            device.bus_number(),
+            device.address(),
+        ));
+        for config_index in 0..desc.num_configurations() {
+            let config = match device.config_descriptor(config_index) {
+                Ok(config) => config,
+                Err(e) => {
+                    lines.push(format!("  Configuration #{config_index}: {e}"));
+                    continue;
+                }
+            };
+            lines.push(format!("  Configuration {}", config.number()));
+            for interface in config.interfaces() {
+                for setting in interface.descriptors() {
+                    lines.push(format!(
+                        "    Interface {} alt {}: class {:02x}/{:02x}/{:02x}",
+                        setting.interface_number(),
+                        setting.setting_number(),
+                        setting.class_code(),
+                        setting.sub_class_code(),
+                        setting.protocol_code(),
+                    ));
+                    for endpoint in setting.endpoint_descriptors() {
+                        lines.push(format!(
+                            "      Endpoint {:#04x}: {:?} {:?}, max packet size {}",
+                            endpoint.address(),
+                            endpoint.direction(),
+                            endpoint.transfer_type(),
+                            endpoint.max_packet_size(),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    if lines.is_empty() {
+        return Err(Error::NotFound);
+    }
+    Ok(lines.join("\n"))
+}
+
+/// A supported device was connected or disconnected. See [`register_hotplug`]
+#[derive(Debug, Clone)]
+pub enum HotplugEvent {
+    /// Glasses were connected. They can be opened with the usual constructors, but
+    /// not from within the callback.
+    Arrived(GlassesInfo),
+    /// Glasses were disconnected. The corresponding [`crate::ARGlasses`] object should be
+    /// dropped; reading it only returns errors from now on.
+    Left(GlassesInfo),
+}
+
+/// Keeps a hotplug callback registered. See [`register_hotplug`]
+///
+/// Dropping it unregisters the callback, and stops the background thread.
+pub struct HotplugRegistration {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for HotplugRegistration {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Call `callback` whenever supported glasses are connected or disconnected.
+///
+/// The callback is called from a background thread, as long as the returned
+/// [`HotplugRegistration`] is alive. Since devices can't be opened from the callback,
+/// [`GlassesInfo::product_string`] and [`GlassesInfo::serial`] are always `None`, and the
+/// model name is a best guess from the USB IDs. For Rokid glasses, where all models share
+/// the same IDs, it is only the product family ("Rokid").
+///
+/// Glasses that are already connected when registering are not reported.
+///
+/// libusb does not support hotplug notifications on some platforms (most notably
+/// Windows). There the connected devices are polled every [`HOTPLUG_POLL_INTERVAL`]
+/// instead, so events may arrive with that much delay.
+pub fn register_hotplug(
+    callback: impl FnMut(HotplugEvent) + Send + 'static,
+) -> Result<HotplugRegistration> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+    let thread = if rusb::has_hotplug() {
+        let registration = HotplugBuilder::new().register(
+            GlobalContext::default(),
+            Box::new(HotplugCallback(callback)),
+        )?;
+        std::thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                let _ = GlobalContext::default().handle_events(Some(Duration::from_millis(100)));
+            }
+            drop(registration);
+        })
+    } else {
+        std::thread::spawn(move || poll_hotplug(callback, &thread_stop))
+    };
+    Ok(HotplugRegistration {
+        stop,
+        thread: Some(thread),
+    })
+}
+
+/// How often the connected devices are listed on platforms without hotplug support.
+/// See [`register_hotplug`]
+pub const HOTPLUG_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Hotplug emulation by periodically listing the connected devices
+fn poll_hotplug(mut callback: impl FnMut(HotplugEvent), stop: &AtomicBool) {
+    let mut known = connected_device_infos();
+    while !stop.load(Ordering::Relaxed) {
+        std::thread::sleep(HOTPLUG_POLL_INTERVAL);
+        let current = connected_device_infos();
+        for info in &known {
+            if !current.iter().any(|other| is_same_device(info, other)) {
+                callback(HotplugEvent::Left(info.clone()));
+            }
+        }
+        for info in &current {
+            if !known.iter().any(|other| is_same_device(info, other)) {
+                callback(HotplugEvent::Arrived(info.clone()));
+            }
+        }
+        known = current;
+    }
+}
+
+fn connected_device_infos() -> Vec<GlassesInfo> {
+    DeviceList::new()
+        .map(|devices| {
+            devices
+                .iter()
+                .filter_map(|device| unopened_device_info(&device))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn is_same_device(a: &GlassesInfo, b: &GlassesInfo) -> bool {
+    a.bus_number == b.bus_number
+        && a.address == b.address
+        && a.vendor_id == b.vendor_id
+        && a.product_id == b.product_id
+}
+
+struct HotplugCallback<F>(F);
+
+impl<F: FnMut(HotplugEvent) + Send> Hotplug<GlobalContext> for HotplugCallback<F> {
+    fn device_arrived(&mut self, device: Device<GlobalContext>) {
+        if let Some(info) = unopened_device_info(&device) {
+            (self.0)(HotplugEvent::Arrived(info));
+        }
+    }
+
+    fn device_left(&mut self, device: Device<GlobalContext>) {
+        if let Some(info) = unopened_device_info(&device) {
+            (self.0)(HotplugEvent::Left(info));
+        }
+    }
+}
+
+/// Info about a supported device, only using data that does not need opening it
+fn unopened_device_info(device: &Device<GlobalContext>) -> Option<GlassesInfo> {
+    let desc = device.device_descriptor().ok()?;
+    Some(GlassesInfo {
+        bus_number: device.bus_number(),
+        address: device.address(),
+        vendor_id: desc.vendor_id(),
+        product_id: desc.product_id(),
+        product_string: None,
+        serial: None,
+        model: known_model(desc.vendor_id(), desc.product_id())?,
+    })
+}
+
+fn known_model(vendor_id: u16, product_id: u16) -> Option<&'static str> {
+    KNOWN_DEVICES
+        .iter()
+        .find(|(vid, pid, _)| *vid == vendor_id && *pid == product_id)
+        .map(|(_, _, model)| *model)
+}
+
+/// Vendor ID, Product ID and model name of every device supported by the enabled features.
+/// Rokid models can't be told apart by their IDs, so they only get the family name here;
+/// [`list_devices`] refines it from the product string.
+const KNOWN_DEVICES: &[(u16, u16, &str)] = &[
+    #[cfg(feature = "rokid")]
+    (
+        crate::rokid::RokidAir::VID,
+        crate::rokid::RokidAir::PID,
+        "Rokid",
+    ),
+    #[cfg(feature = "rokid")]
+    (
+        crate::rokid::RokidAir::VID,
+        crate::rokid::RokidAir::ALT_PID,
+        "Rokid",
+    ),
+    #[cfg(feature = "nreal")]
+    (
+        crate::nreal_air::NrealAir::VID,
+        crate::nreal_air::NrealAir::AIR_PID,
+        "XREAL Air",
+    ),
+    #[cfg(feature = "nreal")]
+    (
+        crate::nreal_air::NrealAir::VID,
+        crate::nreal_air::NrealAir::AIR_2_PID,
+        "XREAL Air 2",
+    ),
+    #[cfg(feature = "nreal")]
+    (
+        crate::nreal_air::NrealAir::VID,
+        crate::nreal_air::NrealAir::AIR_2_PRO_PID,
+        "XREAL Air 2 Pro",
+    ),
+    #[cfg(feature = "nreal")]
+    (
+        crate::nreal_light::NrealLight::MCU_VID,
+        crate::nreal_light::NrealLight::MCU_PID,
+        "Nreal Light",
+    ),
+    #[cfg(feature = "grawoow")]
+    (
+        crate::grawoow::GrawoowG530::MCU_VID,
+        crate::grawoow::GrawoowG530::MCU_PID,
+        "Grawoow G530",
+    ),
+    #[cfg(feature = "mad_gaze")]
+    (
+        crate::mad_gaze::MadGazeGlow::VID,
+        crate::mad_gaze::MadGazeGlow::PID,
+        "Mad Gaze Glow",
+    ),
+];
@@ -0,0 +1,275 @@
+// Copyright (C) 2023, Alex Badics
+// This file is part of ar-drivers-rs
+// Licensed under the MIT license. See LICENSE file in the project root for details.
+
+//! Simple built-in orientation estimation. See [`Fusion`] and [`FusedOrientation`]
+//!
+//! This is a complementary (Mahony style) filter: the gyroscope is integrated, and the
+//! drift is corrected with the accelerometer (pitch and roll) and the magnetometer (yaw).
+//! For more demanding applications, a proper EKF (e.g. the `eskf` crate) is recommended.
+
+use std::collections::VecDeque;
+
+use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
+
+use crate::{ARGlasses, GlassesEvent, Result, Side};
+
+/// Orientation estimation from sensor events, for applications that read the events
+/// themselves. [`FusedOrientation`] wraps this around an [`ARGlasses`].
+///
+/// The orientation is a rotation from the glasses' frame to a world frame where
+/// positive Y is up. Timestamps of the glasses events are in microseconds (even on
+/// models where the device itself uses a different unit), and are used to integrate
+/// the gyroscope.
+pub struct Fusion {
+    gain: f32,
+    orientation: UnitQuaternion<f32>,
+    last_timestamp: Option<u64>,
+    last_magnetometer: Option<Vector3<f32>>,
+}
+
+/// Gaps in the sensor data longer than this (in microseconds) are not integrated
+const MAX_TIME_DELTA: u64 = 500000;
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fusion {
+    /// The default value of the filter gain. See [`Fusion::set_gain`]
+    pub const DEFAULT_GAIN: f32 = 0.5;
+
+    /// Create a filter. The orientation is initialized from the first accelerometer reading.
+    pub fn new() -> Self {
+        Self {
+            gain: Self::DEFAULT_GAIN,
+            orientation: UnitQuaternion::identity(),
+            last_timestamp: None,
+            last_magnetometer: None,
+        }
+    }
+
+    /// Set the filter gain, i.e. how strongly the accelerometer and magnetometer correct
+    /// the integrated gyroscope. Higher values mean less drift, but more noise.
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
+    /// The latest orientation estimate
+    pub fn orientation(&self) -> UnitQuaternion<f32> {
+        self.orientation
+    }
+
+    /// Pose of one eye's display in the world frame: the latest orientation combined with
+    /// [`ARGlasses::imu_to_display_matrix`] of `glasses` (which includes the half-IPD
+    /// offset of that eye). `ipd` is in meters.
+    ///
+    /// The world frame has positive Y up, the same handedness as the IMU frame of the
+    /// glasses, and its origin at the IMU: only rotation is tracked, not position.
+    /// Translations are in meters. The inverse of the result is the view matrix for
+    /// rendering that eye.
+    pub fn eye_pose(&self, glasses: &dyn ARGlasses, side: Side, ipd: f32) -> Isometry3<f64> {
+        let orientation =
+            Isometry3::from_parts(Translation3::identity(), self.orientation.cast::<f64>());
+        orientation * glasses.imu_to_display_matrix(side, ipd)
+    }
+
+    /// Feed an event to the filter. Events other than [`GlassesEvent::AccGyro`] and
+    /// [`GlassesEvent::Magnetometer`] are ignored.
+    ///
+    /// Returns a [`GlassesEvent::Orientation`] with the new estimate after every
+    /// accelerometer+gyroscope event.
+    pub fn update(&mut self, event: &GlassesEvent) -> Option<GlassesEvent> {
+        match *event {
+            GlassesEvent::AccGyro {
+                accelerometer,
+                gyroscope,
+                timestamp,
+            } => {
+                self.update_acc_gyro(accelerometer, gyroscope, timestamp);
+                Some(GlassesEvent::Orientation {
+                    quaternion: self.orientation,
+                    timestamp,
+                })
+            }
+            GlassesEvent::Magnetometer { magnetometer, .. } => {
+                self.last_magnetometer = Some(magnetometer);
+                None
+            }
+            GlassesEvent::ClockReset => {
+                self.last_timestamp = None;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn update_acc_gyro(
+        &mut self,
+        accelerometer: Vector3<f32>,
+        gyroscope: Vector3<f32>,
+        timestamp: u64,
+    ) {
+        let last_timestamp = self.last_timestamp.replace(timestamp);
+        let Some(last_timestamp) = last_timestamp else {
+            // First sample: start from the orientation the accelerometer suggests
+            if let Some(rotation) = UnitQuaternion::rotation_between(&accelerometer, &Vector3::y())
+            {
+                self.orientation = rotation;
+            }
+            return;
+        };
+        if timestamp <= last_timestamp || timestamp - last_timestamp > MAX_TIME_DELTA {
+            return;
+        }
+        let dt = (timestamp - last_timestamp) as f32 / 1000000.0;
+
+        let mut correction = Vector3::zeros();
+        if let Some(accelerometer) = accelerometer.try_normalize(1e-6) {
+            // The accelerometer measures "up" when the glasses are still
+            let estimated_up = self.orientation.inverse_transform_vector(&Vector3::y());
+            correction += accelerometer.cross(&estimated_up);
+        }
+        if let Some(magnetometer) = self.last_magnetometer.and_then(|m| m.try_normalize(1e-6)) {
+            // Only use the horizontal direction of the magnetic field, so that it does
+            // not fight the accelerometer
+            let field = self.orientation * magnetometer;
+            let reference = Vector3::new(
+                0.0,
+                field.y,
+                -(field.x * field.x + field.z * field.z).sqrt(),
+            );
+            let estimated_field = self.orientation.inverse_transform_vector(&reference);
+            correction += magnetometer.cross(&estimated_field);
+        }
+
+        let angular_velocity = gyroscope + correction * self.gain;
+        self.orientation *= UnitQuaternion::from_scaled_axis(angular_velocity * dt);
+    }
+}
+
+/// Wraps any [`ARGlasses`] and emits [`GlassesEvent::Orientation`] events calculated from
+/// the sensor events. See [`Fusion`] for the details of the estimation.
+pub struct FusedOrientation {
+    glasses: Box<dyn ARGlasses>,
+    fusion: Fusion,
+    forward_raw_events: bool,
+    pending_events: VecDeque<GlassesEvent>,
+}
+
+impl FusedOrientation {
+    /// The default value of the filter gain. See [`FusedOrientation::set_gain`]
+    pub const DEFAULT_GAIN: f32 = Fusion::DEFAULT_GAIN;
+
+    /// Wrap connected glasses. Raw events are forwarded by default.
+    pub fn new(glasses: Box<dyn ARGlasses>) -> Self {
+        Self {
+            glasses,
+            fusion: Fusion::new(),
+            forward_raw_events: true,
+            pending_events: Default::default(),
+        }
+    }
+
+    /// Set the filter gain. See [`Fusion::set_gain`]
+    pub fn set_gain(&mut self, gain: f32) {
+        self.fusion.set_gain(gain);
+    }
+
+    /// Set whether the raw sensor events should be returned from
+    /// [`FusedOrientation::read_event`] too, or only the orientation events.
+    pub fn set_forward_raw_events(&mut self, forward_raw_events: bool) {
+        self.forward_raw_events = forward_raw_events;
+    }
+
+    /// The latest orientation estimate
+    pub fn orientation(&self) -> UnitQuaternion<f32> {
+        self.fusion.orientation()
+    }
+
+    /// Pose of one eye's display in the world frame. See [`Fusion::eye_pose`]
+    pub fn eye_pose(&self, side: Side, ipd: f32) -> Isometry3<f64> {
+        self.fusion.eye_pose(self.glasses.as_ref(), side, ipd)
+    }
+
+    /// Access to the wrapped glasses, e.g. for setting the display mode
+    pub fn glasses(&mut self) -> &mut dyn ARGlasses {
+        self.glasses.as_mut()
+    }
+
+    /// Get a single event. Blocks. Raw events are passed through (if enabled), and a
+    /// [`GlassesEvent::Orientation`] is emitted after every accelerometer+gyroscope event.
+    pub fn read_event(&mut self) -> Result<GlassesEvent> {
+        loop {
+            if let Some(event) = self.pending_events.pop_front() {
+                return Ok(event);
+            }
+            let event = self.glasses.read_event()?;
+            let orientation_event = self.fusion.update(&event);
+            if self.forward_raw_events {
+                self.pending_events.push_back(event);
+            }
+            self.pending_events.extend(orientation_event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAVITY: f32 = 9.81;
+
+    fn acc_gyro(
+        accelerometer: Vector3<f32>,
+        gyroscope: Vector3<f32>,
+        timestamp: u64,
+    ) -> GlassesEvent {
+        GlassesEvent::AccGyro {
+            accelerometer,
+            gyroscope,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn stationary_converges_to_gravity() {
+        let mut fusion = Fusion::new();
+        fusion.update(&acc_gyro(Vector3::y() * GRAVITY, Vector3::zeros(), 0));
+        // Tilted forward by 0.3 rad, held still for 20 seconds
+        let tilted = Vector3::new(0.0, 0.3f32.cos(), 0.3f32.sin()) * GRAVITY;
+        for i in 1..=2000 {
+            fusion.update(&acc_gyro(tilted, Vector3::zeros(), i * 10000));
+        }
+        let up = fusion.orientation() * tilted.normalize();
+        assert!((up - Vector3::y()).norm() < 1e-3, "{up}");
+    }
+
+    #[test]
+    fn gyroscope_is_integrated() {
+        let mut fusion = Fusion::new();
+        fusion.set_gain(0.0);
+        let gyroscope = Vector3::new(0.0, 1.0, 0.0);
+        // 1 rad/s around the Y axis for a second
+        for i in 0..=1000 {
+            fusion.update(&acc_gyro(Vector3::y() * GRAVITY, gyroscope, i * 1000));
+        }
+        let expected = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 1.0);
+        assert!(fusion.orientation().angle_to(&expected) < 1e-3);
+    }
+
+    #[test]
+    fn clock_reset_skips_integration() {
+        let mut fusion = Fusion::new();
+        fusion.set_gain(0.0);
+        let gyroscope = Vector3::new(0.0, 1.0, 0.0);
+        fusion.update(&acc_gyro(Vector3::y() * GRAVITY, gyroscope, 400000));
+        fusion.update(&GlassesEvent::ClockReset);
+        fusion.update(&acc_gyro(Vector3::y() * GRAVITY, gyroscope, 0));
+        fusion.update(&acc_gyro(Vector3::y() * GRAVITY, gyroscope, 100000));
+        let expected = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.1);
+        assert!(fusion.orientation().angle_to(&expected) < 1e-3);
+    }
+}
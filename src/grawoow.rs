@@ -62,6 +62,10 @@ impl ARGlasses for GrawoowG530 {
         Ok(())
     }
 
+    fn supported_display_modes(&self) -> &'static [DisplayMode] {
+        &[DisplayMode::SameOnBoth, DisplayMode::Stereo]
+    }
+
     fn display_fov(&self) -> f32 {
         // Measurement result
         22f32.to_radians()
@@ -122,11 +126,11 @@ impl GrawoowG530 {
     /// Only one instance can be alive at a time
     #[cfg(not(target_os = "android"))]
     pub fn new() -> Result<Self> {
-        use crate::util::get_device_vid_pid;
+        use crate::util::{get_device_vid_pid, open_device};
 
         Self::new_common(
-            get_device_vid_pid(Self::MCU_VID, Self::MCU_PID)?.open()?,
-            get_device_vid_pid(Self::OV580_VID, Self::OV580_PID)?.open()?,
+            open_device(&get_device_vid_pid(Self::MCU_VID, Self::MCU_PID)?)?,
+            open_device(&get_device_vid_pid(Self::OV580_VID, Self::OV580_PID)?)?,
         )
     }
 
@@ -140,7 +144,7 @@ impl GrawoowG530 {
         mcu_handle.claim_interface(0)?;
         ov580_handle.claim_interface(
             get_interface_for_endpoint(&ov580_handle.device(), OV580_ENDPOINT).ok_or_else(
-                || Error::Other("Could not find endpoint, wrong USB structure (probably)"),
+                || Error::Other("Endpoint not found, see enumeration::dump_usb_topology()"),
             )?,
         )?;
         let mut result = Self {
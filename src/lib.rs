@@ -19,9 +19,12 @@
 //! }
 //! ```
 //!
-//! As opposed to e.g. Rokid's own API, this is all that you get, since this is what comes
-//! out of the hardware. To get quaternions, you should probably use a lib that implements
-//! Madgwicks algorithm or a proper EKF. One good choice is the `eskf` crate.
+//! As opposed to e.g. Rokid's own API, this is mostly all that you get, since this is what
+//! comes out of the hardware. For basic orientation quaternions, `fusion::Fusion` (or
+//! `fusion::FusedOrientation`, which wraps the glasses themselves, both behind the `fusion`
+//! feature)
+//! can be used, but for anything serious you should probably use a lib that implements
+//! a proper EKF. One good choice is the `eskf` crate.
 //!
 //! ## Feature flags
 //!
@@ -33,19 +36,69 @@
 //!
 //! All of them are enabled by default, which may bring in some unwanted dependencies if you
 //! only want to support a specific type.
+//!
+//! Optional features, not enabled by default:
+//!
+//! * `async`: `RokidAir::read_event_async`, using `tokio`
+//! * `fusion`: `fusion::Fusion` and `fusion::FusedOrientation`, simple built-in orientation
+//!   estimation
+//! * `serde`: `Serialize` and `Deserialize` for [`GlassesEvent`] and the other basic types.
+//!   Vectors and quaternions are serialized as plain arrays.
+//! * `mock`: `MockGlasses`, fake glasses for testing applications without hardware, and
+//!   `transport::MockTransport`, for testing drivers without hardware
+//! * `log`: Logging with the `log` crate. USB transfers and parsed packets are logged at
+//!   trace level, the events returned by `RokidAir` at debug level, and dropped packets
+//!   and samples at warn level.
+
+use std::time::Duration;
 
 use nalgebra::{Isometry3, Matrix3, UnitQuaternion, Vector2, Vector3};
 
+/// `log::trace!` if the `log` feature is enabled, nothing otherwise. Statement only.
+#[allow(unused_macros)]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::trace!($($arg)*);
+    };
+}
+
+/// `log::debug!` if the `log` feature is enabled, nothing otherwise. Statement only.
+#[allow(unused_macros)]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::debug!($($arg)*);
+    };
+}
+
+/// `log::warn!` if the `log` feature is enabled, nothing otherwise. Statement only.
+#[allow(unused_macros)]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::warn!($($arg)*);
+    };
+}
+
+#[cfg(feature = "rusb")]
+#[cfg(not(target_os = "android"))]
+pub mod enumeration;
+#[cfg(feature = "fusion")]
+pub mod fusion;
 #[cfg(feature = "grawoow")]
 pub mod grawoow;
 #[cfg(feature = "mad_gaze")]
 pub mod mad_gaze;
+#[cfg(feature = "mock")]
+pub mod mock;
 #[cfg(feature = "nreal")]
 pub mod nreal_air;
 #[cfg(feature = "nreal")]
 pub mod nreal_light;
 #[cfg(feature = "rokid")]
 pub mod rokid;
+pub mod transport;
 mod util;
 
 /// Possible errors resulting from `ar-drivers` API calls
@@ -64,12 +117,33 @@ pub enum Error {
     SerialPortError(serialport::Error),
     /// No glasses were found.
     NotFound,
+    /// The glasses were found, but the current user has no permission to open them.
+    /// On Linux this usually means that a udev rule is missing, see the error message
+    /// for the exact rule.
+    PermissionDenied {
+        /// USB Vendor ID of the device
+        vendor_id: u16,
+        /// USB Product ID of the device
+        product_id: u16,
+    },
     /// The feature is not available with this headset.
     NotImplemented,
     /// Packet sending or reception timed out. Note that this is not the only
-    /// timeout error that is sent (e.g. UsbError can contain a timeout), and
-    /// also this is usually a fatal one.
+    /// timeout error that is sent (see [`Error::Timeout`]). Like that one, it is
+    /// transient: the glasses may simply have been slow to answer, and the call can be retried.
     PacketTimeout,
+    /// A USB transfer timed out. This is usually not fatal, e.g. it can simply mean
+    /// that no new sensor data arrived in time.
+    Timeout,
+    /// The glasses were disconnected. This is fatal, the glasses have to be reopened.
+    Disconnected,
+    /// The device sent fewer bytes than what was needed to process its response.
+    ShortRead {
+        /// Number of bytes needed
+        expected: usize,
+        /// Number of bytes actually received
+        got: usize,
+    },
     /// Other fatal error, usually a problem with the library itself, or
     /// a device support issue. File a bug if you encounter this.
     Other(&'static str),
@@ -94,31 +168,57 @@ impl std::error::Error for Error {
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(match self {
-            Error::IoError(_) => "I/O error",
+        match self {
+            Error::IoError(e) => write!(f, "I/O error: {e}"),
             #[cfg(feature = "rusb")]
-            Error::UsbError(_) => "Libusb error",
+            Error::UsbError(e) => write!(f, "Libusb error: {e}"),
             #[cfg(feature = "hidapi")]
-            Error::HidError(_) => "Hidapi error",
+            Error::HidError(e) => write!(f, "Hidapi error: {e}"),
             #[cfg(feature = "serialport")]
-            Error::SerialPortError(_) => "Serial error",
-            Error::NotFound => "Glasses not found",
-            Error::NotImplemented => "Not implemented for these glasses",
-            Error::PacketTimeout => "Packet timeout",
-            Error::Other(s) => s,
-        })
+            Error::SerialPortError(e) => write!(f, "Serial error: {e}"),
+            Error::NotFound => f.write_str("Glasses not found"),
+            Error::PermissionDenied {
+                vendor_id,
+                product_id,
+            } => write!(
+                f,
+                "No permission to open the glasses. On Linux, add the following udev rule \
+                 (e.g. to /etc/udev/rules.d/70-ar-glasses.rules), and replug them: {}",
+                udev_rule(*vendor_id, *product_id)
+            ),
+            Error::NotImplemented => f.write_str("Not implemented for these glasses"),
+            Error::PacketTimeout => f.write_str("Packet timeout"),
+            Error::Timeout => f.write_str("Timeout"),
+            Error::Disconnected => f.write_str("Glasses disconnected"),
+            Error::ShortRead { expected, got } => {
+                write!(
+                    f,
+                    "Response too short: expected {expected} bytes, got {got}"
+                )
+            }
+            Error::Other(s) => f.write_str(s),
+        }
     }
 }
 
+/// A udev rule that lets every user access the given device on Linux
+pub(crate) fn udev_rule(vendor_id: u16, product_id: u16) -> String {
+    format!(
+        "SUBSYSTEM==\"usb\", ATTR{{idVendor}}==\"{vendor_id:04x}\", \
+         ATTR{{idProduct}}==\"{product_id:04x}\", MODE=\"0666\""
+    )
+}
+
 /// AR glasses sensor event, got from [`ARGlasses::read_event`]
 ///
 /// Coordinate system is "RUB": Positive X is Right, Positive Y is Up, Positive Z is backwards.
 /// This is the same as the Android sensor coordinate system.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GlassesEvent {
     /// Synchronized accelerometer and gyroscope data.
     AccGyro {
-        /// Accelerometer data in m^2/s.
+        /// Accelerometer data in m/s^2.
         ///
         /// Remember that while gravitational acceleration is "down", the acceleration
         /// the device "feels" is the one opposite from that, so the normal reading
@@ -130,6 +230,22 @@ pub enum GlassesEvent {
         /// Timestamp, in device time, in microseconds
         timestamp: u64,
     },
+    /// Accelerometer data that could not be paired with a gyroscope sample of the same
+    /// timestamp. Same units as in [`GlassesEvent::AccGyro`]. Most glasses never send this.
+    Accelerometer {
+        /// Accelerometer data in m/s^2.
+        accelerometer: Vector3<f32>,
+        /// Timestamp, in device time, in microseconds
+        timestamp: u64,
+    },
+    /// Gyroscope data that could not be paired with an accelerometer sample of the same
+    /// timestamp. Same units as in [`GlassesEvent::AccGyro`]. Most glasses never send this.
+    Gyroscope {
+        /// Gyroscope data in rad/sec.
+        gyroscope: Vector3<f32>,
+        /// Timestamp, in device time, in microseconds
+        timestamp: u64,
+    },
     /// Magnetometer data.
     Magnetometer {
         /// Direction of magnetic north (more or less). Unit is uT.
@@ -140,6 +256,12 @@ pub enum GlassesEvent {
     /// A key was pressed (sent once per press)
     /// The number is a key ID, starting from 0.
     KeyPress(u8),
+    /// A key was released (sent once per release), after a [`GlassesEvent::KeyPress`]
+    /// with the same key ID. Not all glasses report this.
+    KeyRelease(u8),
+    /// A key is still held down. Only sent if key repeat was enabled, e.g. with
+    /// `RokidAir::set_key_repeat`
+    KeyRepeat(u8),
 
     /// Proximity sensor senses the user, i.e. the glasses were put on
     /// Sent once per event.
@@ -148,14 +270,94 @@ pub enum GlassesEvent {
     /// Proximity sensor senses the user, i.e. the glasses were taken off.
     /// Sent once per event.
     ProximityFar,
+    /// The display brightness was changed, e.g. with the buttons on the glasses.
+    /// The value is the raw brightness reported by the glasses. Not sent for the
+    /// initial value.
+    BrightnessChanged(u8),
+    /// The volume was changed, e.g. with the buttons on the glasses.
+    /// The value is the raw volume reported by the glasses. Not sent for the
+    /// initial value.
+    VolumeChanged(u8),
     /// Ambient light level. Unit is vendor-specific
     AmbientLight(u16),
     /// V-sync happened on the device
-    VSync,
+    VSync {
+        /// Time of the v-sync, in device time, in microseconds (same clock as the
+        /// sensor timestamps). `None` if the glasses do not report it.
+        timestamp: Option<u64>,
+    },
+    /// Estimated orientation of the glasses. Only emitted by `fusion::Fusion` and
+    /// `fusion::FusedOrientation`
+    Orientation {
+        /// Rotation from the glasses' frame to a world frame, where positive Y is up.
+        quaternion: UnitQuaternion<f32>,
+        /// Timestamp of the sensor data it was calculated from, in microseconds
+        timestamp: u64,
+    },
+    /// The device clock jumped backwards (e.g. because the glasses rebooted while
+    /// connected). Timestamps of the following events are not comparable to the earlier
+    /// ones, so anything integrating over time should restart from here.
+    ClockReset,
+}
+
+impl GlassesEvent {
+    /// The category of the event, for filtering. See [`ARGlassesExt::read_event_filtered`]
+    pub fn kind(&self) -> EventKind {
+        match self {
+            GlassesEvent::AccGyro { .. }
+            | GlassesEvent::Accelerometer { .. }
+            | GlassesEvent::Gyroscope { .. } => EventKind::ACC_GYRO,
+            GlassesEvent::Magnetometer { .. } => EventKind::MAGNETOMETER,
+            GlassesEvent::KeyPress(_)
+            | GlassesEvent::KeyRelease(_)
+            | GlassesEvent::KeyRepeat(_) => EventKind::KEY,
+            GlassesEvent::ProximityNear | GlassesEvent::ProximityFar => EventKind::PROXIMITY,
+            GlassesEvent::VSync { .. } => EventKind::VSYNC,
+            _ => EventKind::OTHER,
+        }
+    }
+}
+
+/// A set of [`GlassesEvent`] categories. Combine them with `|`, e.g.
+/// `EventKind::ACC_GYRO | EventKind::MAGNETOMETER`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EventKind(u8);
+
+impl EventKind {
+    /// [`GlassesEvent::AccGyro`], and the unpaired [`GlassesEvent::Accelerometer`] and
+    /// [`GlassesEvent::Gyroscope`]
+    pub const ACC_GYRO: Self = Self(1 << 0);
+    /// [`GlassesEvent::Magnetometer`]
+    pub const MAGNETOMETER: Self = Self(1 << 1);
+    /// [`GlassesEvent::KeyPress`], [`GlassesEvent::KeyRelease`] and
+    /// [`GlassesEvent::KeyRepeat`]
+    pub const KEY: Self = Self(1 << 2);
+    /// [`GlassesEvent::ProximityNear`] and [`GlassesEvent::ProximityFar`]
+    pub const PROXIMITY: Self = Self(1 << 3);
+    /// [`GlassesEvent::VSync`]
+    pub const VSYNC: Self = Self(1 << 4);
+    /// All other events, e.g. brightness changes or [`GlassesEvent::ClockReset`]
+    pub const OTHER: Self = Self(1 << 5);
+    /// Every event
+    pub const ALL: Self = Self(0x3f);
+
+    /// Whether all kinds in `other` are in this set
+    pub fn contains(self, other: EventKind) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for EventKind {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
 }
 
 /// Display mode used by [`ARGlasses::set_display_mode`]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DisplayMode {
     /// Picture should be same for both eyes (simple full HD mode)
     SameOnBoth,
@@ -173,6 +375,7 @@ pub enum DisplayMode {
 
 /// Display side used by [`ARGlasses::view_matrix`]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Side {
     /// Left display
     Left,
@@ -185,11 +388,29 @@ pub trait ARGlasses: Send {
     /// Get the serial number of the glasses
     fn serial(&mut self) -> Result<String>;
     /// Get a single sensor event. Blocks.
+    ///
+    /// [`Error::Timeout`] and [`Error::PacketTimeout`] are transient, it is fine to call
+    /// this again after them. [`Error::Disconnected`] is fatal: the glasses were unplugged,
+    /// and the object should be dropped.
     fn read_event(&mut self) -> Result<GlassesEvent>;
     /// Get the display mode of the glasses. See [`DisplayMode`]
     fn get_display_mode(&mut self) -> Result<DisplayMode>;
     /// Set the display mode of the glasses. See [`DisplayMode`]
     fn set_display_mode(&mut self, display_mode: DisplayMode) -> Result<()>;
+    /// Display modes that can be set with [`ARGlasses::set_display_mode`].
+    /// Setting any other mode returns an error.
+    ///
+    /// The default implementation lists every [`DisplayMode`], for glasses that do not
+    /// know better.
+    fn supported_display_modes(&self) -> &'static [DisplayMode] {
+        &[
+            DisplayMode::SameOnBoth,
+            DisplayMode::Stereo,
+            DisplayMode::HalfSBS,
+            DisplayMode::HighRefreshRate,
+            DisplayMode::HighRefreshRateSBS,
+        ]
+    }
     /// Field of view of the display along the horizontal axis, in radians
     fn display_fov(&self) -> f32;
     /// Transformation from IMU frame to display frame, at the specified
@@ -202,6 +423,53 @@ pub trait ARGlasses: Send {
     fn cameras(&self) -> Result<Vec<CameraDescriptor>> {
         Ok(Vec::new())
     }
+    /// Resolution of a single eye's picture in the current display mode, in pixels
+    /// (width, height). For SBS modes this is half of the width the glasses present
+    /// themselves with on DisplayPort.
+    fn display_resolution(&mut self) -> Result<(u32, u32)> {
+        Err(Error::NotImplemented)
+    }
+    /// Refresh rate of the display in the current display mode, in Hz
+    fn refresh_rate(&mut self) -> Result<u32> {
+        Err(Error::NotImplemented)
+    }
+    /// Set the display mode, but only if it is not already active. Changing the mode
+    /// makes the display resync visibly, even if the mode stays the same, so this is the
+    /// preferred way to make sure the glasses are in a given mode (e.g. after connecting).
+    ///
+    /// Returns an error if the mode is not in [`ARGlasses::supported_display_modes`].
+    fn ensure_display_mode(&mut self, display_mode: DisplayMode) -> Result<()> {
+        if !self.supported_display_modes().contains(&display_mode) {
+            return Err(Error::Other("Display mode not supported"));
+        }
+        if self.get_display_mode()? != display_mode {
+            self.set_display_mode(display_mode)?;
+        }
+        Ok(())
+    }
+    /// Set the display mode, then wait until the glasses report it as active, checking
+    /// up to `attempts` times, `poll_interval` apart. Useful to avoid drawing e.g. SBS
+    /// content before the display has actually switched.
+    ///
+    /// The mode is always checked at least once, even if `attempts` is 0.
+    /// Returns an error if the new mode was not confirmed in time.
+    fn set_display_mode_verified(
+        &mut self,
+        display_mode: DisplayMode,
+        poll_interval: Duration,
+        attempts: u32,
+    ) -> Result<()> {
+        self.set_display_mode(display_mode)?;
+        for attempt in 1..=attempts.max(1) {
+            if self.get_display_mode()? == display_mode {
+                return Ok(());
+            }
+            if attempt < attempts {
+                std::thread::sleep(poll_interval);
+            }
+        }
+        Err(Error::Other("Display mode change not confirmed"))
+    }
     /// Get the available display matrices
     fn display_matrices(&self) -> Result<(DisplayMatrices, DisplayMatrices)> {
         Err(Error::NotImplemented)
@@ -213,6 +481,53 @@ pub trait ARGlasses: Send {
     fn display_delay(&self) -> u64;
 }
 
+/// Convenience methods available for all [`ARGlasses`], including `dyn ARGlasses`
+pub trait ARGlassesExt: ARGlasses {
+    /// Iterate over the sensor events. See [`ARGlasses::read_event`]
+    ///
+    /// Timeouts ([`Error::Timeout`] and [`Error::PacketTimeout`]) are returned as items, but
+    /// iteration goes on after them. Any other error (e.g. [`Error::Disconnected`]) is
+    /// returned as the last item, then the iterator ends.
+    fn events(&mut self) -> Events<'_, Self> {
+        Events {
+            glasses: self,
+            finished: false,
+        }
+    }
+
+    /// Get the next event whose kind is in `mask`. Blocks. Other events are read and
+    /// discarded. See [`ARGlasses::read_event`]
+    fn read_event_filtered(&mut self, mask: EventKind) -> Result<GlassesEvent> {
+        loop {
+            let event = self.read_event()?;
+            if mask.contains(event.kind()) {
+                return Ok(event);
+            }
+        }
+    }
+}
+
+impl<G: ARGlasses + ?Sized> ARGlassesExt for G {}
+
+/// Iterator over the sensor events of glasses. See [`ARGlassesExt::events`]
+pub struct Events<'a, G: ARGlasses + ?Sized> {
+    glasses: &'a mut G,
+    finished: bool,
+}
+
+impl<'a, G: ARGlasses + ?Sized> Iterator for Events<'a, G> {
+    type Item = Result<GlassesEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let result = self.glasses.read_event();
+        self.finished = matches!(result, Err(ref e) if !e.is_transient());
+        Some(result)
+    }
+}
+
 /// Represents one built-in camera
 ///
 /// Warning: Experimental. May change between any versions.
@@ -244,29 +559,69 @@ pub struct DisplayMatrices {
 }
 
 /// Convenience function to detect and connect to any of the supported glasses
+///
+/// If no glasses could be opened, but some were found and failed to open (e.g. with
+/// [`Error::PermissionDenied`]), that error is returned instead of [`Error::NotFound`],
+/// so that problems with connected glasses are not hidden.
 #[cfg(not(target_os = "android"))]
 pub fn any_glasses() -> Result<Box<dyn ARGlasses>> {
-    #[cfg(feature = "rokid")]
-    if let Ok(glasses) = rokid::RokidAir::new() {
-        return Ok(Box::new(glasses));
-    };
-    #[cfg(feature = "nreal")]
-    if let Ok(glasses) = nreal_air::NrealAir::new() {
-        return Ok(Box::new(glasses));
-    };
-    #[cfg(feature = "nreal")]
-    if let Ok(glasses) = nreal_light::NrealLight::new() {
-        return Ok(Box::new(glasses));
-    };
-    #[cfg(feature = "grawoow")]
-    if let Ok(glasses) = grawoow::GrawoowG530::new() {
-        return Ok(Box::new(glasses));
-    };
-    #[cfg(feature = "mad_gaze")]
-    if let Ok(glasses) = mad_gaze::MadGazeGlow::new() {
-        return Ok(Box::new(glasses));
-    };
-    Err(Error::NotFound)
+    first_glasses(&[
+        #[cfg(feature = "rokid")]
+        || boxed(rokid::RokidAir::new()),
+        #[cfg(feature = "nreal")]
+        || boxed(nreal_air::NrealAir::new()),
+        #[cfg(feature = "nreal")]
+        || boxed(nreal_light::NrealLight::new()),
+        #[cfg(feature = "grawoow")]
+        || boxed(grawoow::GrawoowG530::new()),
+        #[cfg(feature = "mad_gaze")]
+        || boxed(mad_gaze::MadGazeGlow::new()),
+    ])
+}
+
+#[cfg(not(target_os = "android"))]
+#[allow(dead_code)]
+fn boxed(glasses: Result<impl ARGlasses + 'static>) -> Result<Box<dyn ARGlasses>> {
+    Ok(Box::new(glasses?))
+}
+
+#[cfg(not(target_os = "android"))]
+type GlassesConstructor = fn() -> Result<Box<dyn ARGlasses>>;
+
+/// The glasses opened by the first successful constructor. See [`any_glasses`]
+#[cfg(not(target_os = "android"))]
+fn first_glasses(constructors: &[GlassesConstructor]) -> Result<Box<dyn ARGlasses>> {
+    let mut first_error = None;
+    for constructor in constructors {
+        match constructor() {
+            Ok(glasses) => return Ok(glasses),
+            Err(e) if first_error.is_none() && e.is_from_connected_device() => {
+                first_error = Some(e)
+            }
+            // Most drivers report missing glasses in their own way (e.g. hidapi errors)
+            Err(_) => (),
+        }
+    }
+    Err(first_error.unwrap_or(Error::NotFound))
+}
+
+impl Error {
+    /// Whether reading events can simply go on after this error. See [`ARGlasses::read_event`]
+    pub(crate) fn is_transient(&self) -> bool {
+        matches!(self, Error::Timeout | Error::PacketTimeout)
+    }
+
+    /// Whether the error surely came from glasses that are connected, as opposed to e.g.
+    /// not finding them
+    #[cfg(not(target_os = "android"))]
+    fn is_from_connected_device(&self) -> bool {
+        match self {
+            Error::PermissionDenied { .. } | Error::Disconnected => true,
+            #[cfg(feature = "rusb")]
+            Error::UsbError(rusb::Error::Busy | rusb::Error::Access) => true,
+            _ => false,
+        }
+    }
 }
 
 impl From<std::io::Error> for Error {
@@ -278,7 +633,11 @@ impl From<std::io::Error> for Error {
 #[cfg(feature = "rusb")]
 impl From<rusb::Error> for Error {
     fn from(e: rusb::Error) -> Self {
-        Error::UsbError(e)
+        match e {
+            rusb::Error::Timeout => Error::Timeout,
+            rusb::Error::NoDevice | rusb::Error::Io => Error::Disconnected,
+            e => Error::UsbError(e),
+        }
     }
 }
 
@@ -301,3 +660,111 @@ impl From<&'static str> for Error {
         Error::Other(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(target_os = "android"))]
+    fn nothing_connected() {
+        let result = first_glasses(&[
+            || Err(Error::NotFound),
+            || Err(Error::Other("hidapi: device not found")),
+            || Err(Error::IoError(std::io::ErrorKind::NotFound.into())),
+        ]);
+        assert!(matches!(result, Err(Error::NotFound)));
+        assert!(matches!(first_glasses(&[]), Err(Error::NotFound)));
+    }
+
+    #[test]
+    #[cfg(not(target_os = "android"))]
+    fn connected_device_error_is_kept() {
+        let result = first_glasses(&[
+            || Err(Error::NotFound),
+            || {
+                Err(Error::PermissionDenied {
+                    vendor_id: 1,
+                    product_id: 2,
+                })
+            },
+            || Err(Error::Disconnected),
+        ]);
+        assert!(matches!(
+            result,
+            Err(Error::PermissionDenied {
+                vendor_id: 1,
+                product_id: 2
+            })
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let vector = Vector3::new(1.0, 2.5, -3.0);
+        let events = [
+            GlassesEvent::AccGyro {
+                accelerometer: vector,
+                gyroscope: -vector,
+                timestamp: 1,
+            },
+            GlassesEvent::Accelerometer {
+                accelerometer: vector,
+                timestamp: 2,
+            },
+            GlassesEvent::Gyroscope {
+                gyroscope: vector,
+                timestamp: 3,
+            },
+            GlassesEvent::Magnetometer {
+                magnetometer: vector,
+                timestamp: 4,
+            },
+            GlassesEvent::KeyPress(1),
+            GlassesEvent::KeyRelease(2),
+            GlassesEvent::KeyRepeat(3),
+            GlassesEvent::ProximityNear,
+            GlassesEvent::ProximityFar,
+            GlassesEvent::BrightnessChanged(50),
+            GlassesEvent::VolumeChanged(60),
+            GlassesEvent::AmbientLight(1000),
+            GlassesEvent::VSync { timestamp: Some(5) },
+            GlassesEvent::VSync { timestamp: None },
+            GlassesEvent::Orientation {
+                quaternion: UnitQuaternion::from_euler_angles(0.1, 0.2, 0.3),
+                timestamp: 6,
+            },
+            GlassesEvent::ClockReset,
+        ];
+        for event in events {
+            let json = serde_json::to_string(&event).unwrap();
+            let decoded: GlassesEvent = serde_json::from_str(&json).unwrap();
+            assert_eq!(format!("{decoded:?}"), format!("{event:?}"), "{json}");
+        }
+        // Vectors are plain arrays
+        let json = serde_json::to_string(&GlassesEvent::Accelerometer {
+            accelerometer: vector,
+            timestamp: 7,
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            r#"{"Accelerometer":{"accelerometer":[1.0,2.5,-3.0],"timestamp":7}}"#
+        );
+        for mode in [
+            DisplayMode::SameOnBoth,
+            DisplayMode::Stereo,
+            DisplayMode::HalfSBS,
+            DisplayMode::HighRefreshRate,
+            DisplayMode::HighRefreshRateSBS,
+        ] {
+            let json = serde_json::to_string(&mode).unwrap();
+            assert_eq!(serde_json::from_str::<DisplayMode>(&json).unwrap(), mode);
+        }
+        for side in [Side::Left, Side::Right] {
+            let json = serde_json::to_string(&side).unwrap();
+            assert_eq!(serde_json::from_str::<Side>(&json).unwrap(), side);
+        }
+    }
+}
@@ -95,6 +95,10 @@ impl ARGlasses for MadGazeGlow {
         }
     }
 
+    fn supported_display_modes(&self) -> &'static [DisplayMode] {
+        &[DisplayMode::SameOnBoth, DisplayMode::Stereo]
+    }
+
     fn display_fov(&self) -> f32 {
         // The 23.5 degrees here is an actual measurement result
         //
@@ -133,6 +137,11 @@ const BMI160_ADDRESS: u8 = 104;
 const MAGNETOMETER_PERIOD: u64 = 50000;
 
 impl MadGazeGlow {
+    /// Vendor ID of the USB serial port the glasses are reached through
+    pub const VID: u16 = 0x04b4;
+    /// Product ID of the USB serial port the glasses are reached through
+    pub const PID: u16 = 0x0002;
+
     /// Find a connected Mad Gaze Glow device and connect to it.
     /// Only one instance should be alive at a time.
     pub fn new() -> Result<Self> {
@@ -293,8 +302,8 @@ impl SerialFraming {
                 matches!(
                     p.port_type,
                     SerialPortType::UsbPort(UsbPortInfo {
-                        vid: 1204,
-                        pid: 2,
+                        vid: MadGazeGlow::VID,
+                        pid: MadGazeGlow::PID,
                         ..
                     })
                 )
@@ -0,0 +1,96 @@
+// Copyright (C) 2023, Alex Badics
+// This file is part of ar-drivers-rs
+// Licensed under the MIT license. See LICENSE file in the project root for details.
+
+//! Fake glasses for testing applications without hardware. See [`MockGlasses`]
+
+use std::collections::VecDeque;
+
+use nalgebra::{Isometry3, Translation3};
+
+use crate::{ARGlasses, DisplayMode, Error, GlassesEvent, Result, Side};
+
+/// Glasses that replay a predefined list of events, e.g. ones recorded earlier with the
+/// `serde` feature.
+///
+/// After the last event, [`ARGlasses::read_event`] returns [`Error::Disconnected`].
+pub struct MockGlasses {
+    events: VecDeque<GlassesEvent>,
+    serial: String,
+    name: &'static str,
+    display_mode: DisplayMode,
+    display_fov: f32,
+}
+
+impl MockGlasses {
+    /// Create mock glasses that will return `events` in order
+    pub fn new(events: impl IntoIterator<Item = GlassesEvent>) -> Self {
+        Self {
+            events: events.into_iter().collect(),
+            serial: "MOCK".into(),
+            name: "Mock glasses",
+            display_mode: DisplayMode::SameOnBoth,
+            display_fov: 20f32.to_radians(),
+        }
+    }
+
+    /// Queue an additional event, after the already queued ones
+    pub fn push_event(&mut self, event: GlassesEvent) {
+        self.events.push_back(event);
+    }
+
+    /// Set the value returned by [`ARGlasses::serial`]
+    pub fn set_serial(&mut self, serial: &str) {
+        self.serial = serial.into();
+    }
+
+    /// Set the value returned by [`ARGlasses::name`]
+    pub fn set_name(&mut self, name: &'static str) {
+        self.name = name;
+    }
+
+    /// Set the value returned by [`ARGlasses::display_fov`], in radians
+    pub fn set_display_fov(&mut self, display_fov: f32) {
+        self.display_fov = display_fov;
+    }
+}
+
+impl ARGlasses for MockGlasses {
+    fn serial(&mut self) -> Result<String> {
+        Ok(self.serial.clone())
+    }
+
+    fn read_event(&mut self) -> Result<GlassesEvent> {
+        self.events.pop_front().ok_or(Error::Disconnected)
+    }
+
+    fn get_display_mode(&mut self) -> Result<DisplayMode> {
+        Ok(self.display_mode)
+    }
+
+    fn set_display_mode(&mut self, display_mode: DisplayMode) -> Result<()> {
+        self.display_mode = display_mode;
+        Ok(())
+    }
+
+    fn display_fov(&self) -> f32 {
+        self.display_fov
+    }
+
+    fn imu_to_display_matrix(&self, side: Side, ipd: f32) -> Isometry3<f64> {
+        let ipd = ipd as f64
+            * match side {
+                Side::Left => -0.5,
+                Side::Right => 0.5,
+            };
+        Translation3::new(ipd, 0.0, 0.0).into()
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn display_delay(&self) -> u64 {
+        0
+    }
+}
@@ -122,6 +122,16 @@ impl ARGlasses for NrealAir {
         }
     }
 
+    fn supported_display_modes(&self) -> &'static [DisplayMode] {
+        &[
+            DisplayMode::SameOnBoth,
+            DisplayMode::Stereo,
+            DisplayMode::HalfSBS,
+            DisplayMode::HighRefreshRate,
+            DisplayMode::HighRefreshRateSBS,
+        ]
+    }
+
     // TODO
     fn display_fov(&self) -> f32 {
         // This is a judgement call. The displays have a non-trivial distortion,
@@ -166,6 +176,12 @@ impl NrealAir {
     /// Product ID of the NReal Air 1's components
     #[deprecated]
     pub const PID: u16 = AIR_PID;
+    /// Product ID of the XREAL Air (original)
+    pub const AIR_PID: u16 = AIR_PID;
+    /// Product ID of the XREAL Air 2
+    pub const AIR_2_PID: u16 = AIR_2_PID;
+    /// Product ID of the XREAL Air 2 Pro
+    pub const AIR_2_PRO_PID: u16 = AIR_2_PRO_PID;
 
     const DISPLAY_DIVERGENCE: f64 = 0.017;
 
@@ -97,6 +97,15 @@ impl ARGlasses for NrealLight {
         }
     }
 
+    fn supported_display_modes(&self) -> &'static [DisplayMode] {
+        &[
+            DisplayMode::SameOnBoth,
+            DisplayMode::Stereo,
+            DisplayMode::HalfSBS,
+            DisplayMode::HighRefreshRateSBS,
+        ]
+    }
+
     fn display_fov(&self) -> f32 {
         // 24.0° is the advertised FOV
         // According to measurements, it is even a bit better
@@ -258,7 +267,7 @@ impl NrealLight {
                 category: b'5',
                 cmd_id: b'S',
                 ..
-            } => Some(GlassesEvent::VSync),
+            } => Some(GlassesEvent::VSync { timestamp: None }),
             // NOTE: maybe we should retry right here instead of basically reporting timeout,
             //       but we will be called again soon enough.
             _ => None,
@@ -638,8 +647,11 @@ impl NrealLightSlamCamera {
     /// Only one instance can be alive at a time
     #[cfg(not(target_os = "android"))]
     pub fn new() -> Result<Self> {
-        use crate::util::get_device_vid_pid;
-        Self::new_common(get_device_vid_pid(NrealLight::OV580_VID, NrealLight::OV580_PID)?.open()?)
+        use crate::util::{get_device_vid_pid, open_device};
+        Self::new_common(open_device(&get_device_vid_pid(
+            NrealLight::OV580_VID,
+            NrealLight::OV580_PID,
+        )?)?)
     }
 
     fn new_common(mut device_handle: rusb::DeviceHandle<rusb::GlobalContext>) -> Result<Self> {
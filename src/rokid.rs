@@ -5,189 +5,295 @@
 //! Rokid Air AR glasses support. See [`RokidAir`]
 //! It only uses [`rusb`] for communication.
 
-use std::{collections::VecDeque, time::Duration};
+use std::{
+    collections::VecDeque,
+    io::Read,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
+use byteorder::{LittleEndian, ReadBytesExt};
 use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
-use rusb::{request_type, DeviceHandle, GlobalContext};
+use rusb::{DeviceHandle, GlobalContext};
 
+#[cfg(not(target_os = "android"))]
+use crate::enumeration::GlassesInfo;
 use crate::{
-    util::get_interface_for_endpoint, ARGlasses, DisplayMode, Error, GlassesEvent, Result, Side,
+    transport::{ConnectionInfo, Transport, UsbTransport},
+    util::{get_interface_for_endpoint, open_device},
+    ARGlasses, DisplayMode, Error, GlassesEvent, Result, Side,
 };
 
 /// The main structure representing a connected Rokid Air glasses
 pub struct RokidAir {
-    device_handle: DeviceHandle<GlobalContext>,
+    transport: Arc<dyn Transport>,
+    endpoint: u8,
+    timeout: Duration,
     last_accelerometer: Option<(Vector3<f32>, u64)>,
     last_gyroscope: Option<(Vector3<f32>, u64)>,
+    /// Maximum timestamp difference of paired accelerometer and gyroscope samples, in
+    /// microseconds
+    acc_gyro_tolerance: u64,
+    gyro_bias: Vector3<f32>,
+    accelerometer_tolerance: Option<f32>,
+    dropped_sample_count: u64,
+    imu_rate: Option<f32>,
     previous_key_states: u8,
-    proxy_sensor_was_far: bool,
+    key_repeat_interval: Option<Duration>,
+    /// Device timestamp (in microseconds) of the last press or repeat event of each key
+    key_repeat_timestamps: [u64; 8],
+    /// Device timestamp of the latest sensor packet, in microseconds
+    last_timestamp: u64,
+    /// `None` until the first packet with proximity data
+    proxy_sensor_was_far: Option<bool>,
+    proximity_debounce: u8,
+    /// Number of consecutive packets that disagreed with `proxy_sensor_was_far`
+    proximity_change_count: u8,
+    last_reported_brightness: Option<u8>,
+    last_reported_volume: Option<u8>,
+    last_vsync_timestamp: u64,
     pending_events: VecDeque<GlassesEvent>,
     model: RokidModel,
+    /// `None` if not connected through USB directly
+    connection_info: Option<ConnectionInfo>,
+    raw_packet_sink: Option<Sender<[u8; 0x40]>>,
 }
 
-enum RokidModel {
+/// The specific model of the Rokid glasses. See [`RokidAir::model`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RokidModel {
+    /// Rokid Air
     Air,
+    /// Rokid Max
     Max,
+    /// A model this library does not know about (e.g. a newer one).
+    /// Conservative defaults are used for the display parameters.
+    Unknown,
+}
+
+impl RokidModel {
+    /// Detect the model from the USB product string. All models use the same PID, so
+    /// this is the only way to tell them apart.
+    ///
+    /// The model name is expected at the end (so that e.g. a "Max 2" is not taken for a
+    /// "Max"), but the exact strings are not verified, so a name anywhere in the string
+    /// (e.g. followed by a revision) is accepted too.
+    pub(crate) fn from_product_string(product_string: &str) -> Self {
+        let product_string = product_string.trim_end();
+        MODELS
+            .iter()
+            .find(|info| product_string.ends_with(info.product_name))
+            .or_else(|| {
+                MODELS
+                    .iter()
+                    .find(|info| product_string.contains(info.product_name))
+            })
+            .map_or(RokidModel::Unknown, |info| info.model)
+    }
+
+    /// Human readable name of the model
+    pub fn name(&self) -> &'static str {
+        self.info().name
+    }
+
+    fn info(&self) -> &'static ModelInfo {
+        MODELS
+            .iter()
+            .find(|info| info.model == *self)
+            .unwrap_or(&UNKNOWN_MODEL)
+    }
+}
+
+struct ModelInfo {
+    model: RokidModel,
+    /// The end of the USB product string, e.g. "Max" for "Rokid Max"
+    product_name: &'static str,
+    name: &'static str,
+    fov_degrees: f32,
+    /// Tilt of the displays compared to the IMU (around the X axis), in radians
+    tilt: f64,
+    /// Display delay in microseconds
+    display_delay: u64,
+    /// Display modes accepted by [`ARGlasses::set_display_mode`]
+    display_modes: &'static [DisplayMode],
 }
 
+/// Every mode the Rokid protocol has a number for. Which ones actually work was not
+/// verified per model, so all known models use this list for now.
+const ALL_DISPLAY_MODES: &[DisplayMode] = &[
+    DisplayMode::SameOnBoth,
+    DisplayMode::Stereo,
+    DisplayMode::HalfSBS,
+    DisplayMode::HighRefreshRate,
+    DisplayMode::HighRefreshRateSBS,
+];
+
+const MODELS: &[ModelInfo] = &[
+    ModelInfo {
+        model: RokidModel::Air,
+        product_name: "Air",
+        name: "Rokid Air",
+        // 21° is the advertised FOV
+        // 20° is the (dynamically) measured one. It works better with normal PD settings
+        fov_degrees: 20.0,
+        tilt: 0.022,
+        display_delay: 15000,
+        display_modes: ALL_DISPLAY_MODES,
+    },
+    ModelInfo {
+        model: RokidModel::Max,
+        product_name: "Max",
+        name: "Rokid Max",
+        // Measured
+        fov_degrees: 23.0,
+        tilt: 0.07,
+        display_delay: 13000,
+        display_modes: ALL_DISPLAY_MODES,
+    },
+];
+
+/// Modes of the first Rokid glasses, for models not known by this library
+const BASIC_DISPLAY_MODES: &[DisplayMode] = &[DisplayMode::SameOnBoth, DisplayMode::Stereo];
+
+/// Used for models not in [`MODELS`]. Same as the Air, which has the smallest FOV, and
+/// only the basic display modes
+const UNKNOWN_MODEL: ModelInfo = ModelInfo {
+    model: RokidModel::Unknown,
+    product_name: "",
+    name: "Rokid (unknown model)",
+    fov_degrees: 20.0,
+    tilt: 0.022,
+    display_delay: 15000,
+    display_modes: BASIC_DISPLAY_MODES,
+};
+
 /* This is actually hardcoded in the SDK too, except for PID==0x162d, where it's 0x83 */
 const INTERRUPT_IN_ENDPOINT: u8 = 0x82;
+const INTERRUPT_IN_ENDPOINT_ALT: u8 = 0x83;
+
+/// The endpoint the sensor data is read from, based on the product ID
+fn interrupt_endpoint(product_id: u16) -> u8 {
+    if product_id == RokidAir::ALT_PID {
+        INTERRUPT_IN_ENDPOINT_ALT
+    } else {
+        INTERRUPT_IN_ENDPOINT
+    }
+}
+
+const STANDARD_GRAVITY: f32 = 9.81;
+
+/// Shortest timeout used for USB reads. libusb has millisecond resolution, and treats a
+/// zero timeout as infinite.
+const MIN_READ_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// See [`RokidAir::set_accelerometer_tolerance`]
+const DEFAULT_ACCELEROMETER_TOLERANCE: f32 = 10.0;
+
+/// Accelerometer variance (in (m/s^2)^2, summed over the axes) above which the glasses
+/// are considered to have moved during [`RokidAir::estimate_gyro_bias`]. Chosen to be
+/// well above the noise of typical MEMS accelerometers.
+const MAX_STILL_ACCELEROMETER_VARIANCE: f64 = 0.1;
+
+/// Backward jumps of the device clock larger than this (in microseconds) are reported as
+/// [`GlassesEvent::ClockReset`]. Smaller ones are normal: the sensors are sampled
+/// independently, so their packets are not strictly ordered by timestamp.
+const CLOCK_RESET_THRESHOLD: u64 = 500000;
 
-const TIMEOUT: Duration = Duration::from_millis(250);
+/// Raw brightness percentages corresponding to the 1-6 brightness levels
+const BRIGHTNESS_LEVELS: [u8; 6] = [10, 30, 45, 60, 80, 100];
+
+/// Corrupted packets may contain NaNs, infinities or garbage that decodes as denormals
+fn is_valid_vector(vector: &Vector3<f32>) -> bool {
+    vector.iter().all(|c| c.is_finite() && !c.is_subnormal())
+}
+
+/// The display mode number used by the glasses for `display_mode`.
+/// Every mode round-trips through [`raw_to_display_mode`].
+fn display_mode_to_raw(display_mode: DisplayMode) -> u8 {
+    match display_mode {
+        DisplayMode::SameOnBoth => 0,
+        DisplayMode::Stereo => 1,
+        DisplayMode::HalfSBS => 2,
+        DisplayMode::HighRefreshRate => 3,
+        DisplayMode::HighRefreshRateSBS => 4,
+    }
+}
+
+/// Convert a display mode number reported by the glasses.
+/// 5 is a mode of the official software that is never written by this library (read-only);
+/// it is reported as [`DisplayMode::HighRefreshRate`]. Other numbers are an error.
+fn raw_to_display_mode(raw: u8) -> Result<DisplayMode> {
+    match raw {
+        0 => Ok(DisplayMode::SameOnBoth),
+        1 => Ok(DisplayMode::Stereo),
+        2 => Ok(DisplayMode::HalfSBS),
+        3 | 5 => Ok(DisplayMode::HighRefreshRate),
+        4 => Ok(DisplayMode::HighRefreshRateSBS),
+        _ => Err(Error::Other("Unknown display mode")),
+    }
+}
 
 impl ARGlasses for RokidAir {
     fn serial(&mut self) -> Result<String> {
-        let mut result = [0u8; 0x40];
-        self.device_handle.read_control(
-            request_type(
-                rusb::Direction::In,
-                rusb::RequestType::Vendor,
-                rusb::Recipient::Device,
-            ),
-            0x81,
-            0x100,
-            0,
-            &mut result,
-            TIMEOUT,
-        )?;
-        Ok(
-            String::from_utf8(result.iter().copied().take_while(|c| *c != 0).collect())
-                .map_err(|_| "Invalid serial string")?,
-        )
+        Self::read_serial(self.transport.as_ref(), self.timeout)
     }
 
     fn read_event(&mut self) -> Result<GlassesEvent> {
         while self.pending_events.is_empty() {
             let mut packet_data = [0u8; 0x40];
-            self.device_handle
-                .read_interrupt(INTERRUPT_IN_ENDPOINT, &mut packet_data, TIMEOUT)?;
-            match packet_data[0] {
-                2 => {
-                    let packet: &MiscPacket = bytemuck::cast_ref(&packet_data);
-                    self.handle_key_press(packet.keys_pressed);
-                    self.handle_proxy_sensor(packet.proxy_sensor);
-                }
-                4 => {
-                    let packet: &SensorPacket = bytemuck::cast_ref(&packet_data);
-                    let sensor_data =
-                        Vector3::from_data(nalgebra::ArrayStorage([packet.vector; 1]));
-                    match packet.sensor_type {
-                        1 => self.last_accelerometer = Some((sensor_data, packet.timestamp)),
-                        2 => self.last_gyroscope = Some((sensor_data, packet.timestamp)),
-                        // TODO: Magnetometer apparently gives an accuracy value too
-                        3 => self.pending_events.push_back(GlassesEvent::Magnetometer {
-                            magnetometer: sensor_data,
-                            timestamp: packet.timestamp,
-                        }),
-                        _ => (),
-                    }
-                    if let (Some((accelerometer, acc_ts)), Some((gyroscope, gyro_ts))) =
-                        (self.last_accelerometer, self.last_gyroscope)
-                    {
-                        if acc_ts == gyro_ts {
-                            self.last_gyroscope = None;
-                            self.last_accelerometer = None;
-                            self.pending_events.push_back(GlassesEvent::AccGyro {
-                                accelerometer,
-                                gyroscope,
-                                timestamp: acc_ts,
-                            });
-                        }
-                    }
-                }
-                17 => {
-                    let packet: &CombinedPacket = bytemuck::cast_ref(&packet_data);
-                    let timestamp = packet.timestamp / 1000;
-                    self.pending_events.push_back(GlassesEvent::AccGyro {
-                        accelerometer: Vector3::from_data(nalgebra::ArrayStorage(
-                            [packet.accelerometer; 1],
-                        )),
-                        gyroscope: Vector3::from_data(nalgebra::ArrayStorage(
-                            [packet.gyroscope; 1],
-                        )),
-                        timestamp,
-                    });
-                    self.pending_events.push_back(GlassesEvent::Magnetometer {
-                        magnetometer: Vector3::from_data(nalgebra::ArrayStorage(
-                            [packet.magnetometer; 1],
-                        )),
-                        timestamp,
-                    });
-                    // NOTE: was always zero on my Max
-                    self.handle_key_press(packet.keys_pressed);
-                    self.handle_proxy_sensor(packet.proxy_sensor);
-                }
-                _ => {}
-            }
+            let length =
+                self.transport
+                    .read_interrupt(self.endpoint, &mut packet_data, self.timeout)?;
+            self.handle_packet(&packet_data, length);
         }
-        Ok(self.pending_events.pop_front().unwrap())
+        Ok(self.pop_event().unwrap())
     }
 
     fn get_display_mode(&mut self) -> Result<DisplayMode> {
-        let mut result = [0; 0x40];
-        self.device_handle.read_control(
-            request_type(
-                rusb::Direction::In,
-                rusb::RequestType::Vendor,
-                rusb::Recipient::Device,
-            ),
-            0x81,
-            0x0,
-            0x1,
-            &mut result,
-            TIMEOUT,
-        )?;
-        match result[1] {
-            0 => Ok(DisplayMode::SameOnBoth),
-            1 => Ok(DisplayMode::Stereo),
-            2 => Ok(DisplayMode::HalfSBS),
-            4 => Ok(DisplayMode::HighRefreshRateSBS),
-            _ => Ok(DisplayMode::HighRefreshRate),
-        }
+        raw_to_display_mode(self.raw_display_mode()?)
     }
 
     fn set_display_mode(&mut self, display_mode: DisplayMode) -> Result<()> {
-        let display_mode = match display_mode {
-            DisplayMode::SameOnBoth => 0,
-            DisplayMode::Stereo => 1,
-            DisplayMode::HighRefreshRate => 3,
-            DisplayMode::HighRefreshRateSBS => 4,
-            _ => return Err(Error::Other("Display mode not supported")),
-        };
-        self.device_handle.write_control(
-            request_type(
-                rusb::Direction::Out,
-                rusb::RequestType::Vendor,
-                rusb::Recipient::Device,
-            ),
-            0x1,
-            display_mode,
-            0x1,
-            &[0u8; 1],
-            TIMEOUT,
-        )?;
-        Ok(())
+        if !self.supported_display_modes().contains(&display_mode) {
+            return Err(Error::Other("Display mode not supported"));
+        }
+        let display_mode = display_mode_to_raw(display_mode);
+        self.write_value(0x1, display_mode as u16, 0x1, &[0u8; 1])
+    }
+
+    /// Depends on the model. For the known models it is every display mode of the Rokid
+    /// protocol, which is not verified per model yet, so setting a mode the connected
+    /// model can't do may still be ignored by the glasses. Unknown models only get the
+    /// basic 2D and 3D modes.
+    fn supported_display_modes(&self) -> &'static [DisplayMode] {
+        self.model.info().display_modes
+    }
+
+    fn display_resolution(&mut self) -> Result<(u32, u32)> {
+        Ok(match self.get_display_mode()? {
+            DisplayMode::HalfSBS => (960, 1080),
+            _ => (1920, 1080),
+        })
+    }
+
+    fn refresh_rate(&mut self) -> Result<u32> {
+        Ok(match self.get_display_mode()? {
+            // NOTE: Not measured for the SBS variant, assumed to be the same as the 2D one
+            DisplayMode::HighRefreshRate | DisplayMode::HighRefreshRateSBS => 120,
+            _ => 60,
+        })
     }
 
     fn display_fov(&self) -> f32 {
-        match self.model {
-            RokidModel::Air => {
-                // 21° is the advertised FOV
-                // 20° is the (dynamically) measured one. It works better with normal PD settings
-                20f32.to_radians()
-            }
-            RokidModel::Max => {
-                // Measured
-                23f32.to_radians()
-            }
-        }
+        self.model.info().fov_degrees.to_radians()
     }
 
     fn imu_to_display_matrix(&self, side: Side, ipd: f32) -> Isometry3<f64> {
-        let tilt = match self.model {
-            RokidModel::Air => 0.022,
-            RokidModel::Max => 0.07,
-        };
+        let tilt = self.model.info().tilt;
         let ipd = ipd as f64
             * match side {
                 Side::Left => -0.5,
@@ -197,25 +303,22 @@ impl ARGlasses for RokidAir {
     }
 
     fn name(&self) -> &'static str {
-        match self.model {
-            RokidModel::Air => "Rokid Air",
-            RokidModel::Max => "Rokid Max",
-        }
+        self.model.name()
     }
 
     fn display_delay(&self) -> u64 {
-        match self.model {
-            RokidModel::Air => 15000,
-            RokidModel::Max => 13000,
-        }
+        self.model.info().display_delay
     }
 }
 
+// Packets are parsed field by field (instead of casting the buffer into a packed struct),
+// so that multi-byte fields are little endian regardless of the host. Unknown fields are
+// read too, so that parsing fails on incomplete packets (e.g. because of a flaky
+// connection), instead of interpreting the stale or zeroed rest of the buffer.
+
 #[derive(Debug, Clone, Copy)]
-#[repr(C, packed)]
 struct MiscPacket {
-    packet_type: u8,
-    seq: u32,
+    _seq: u32,
     _unknown_0: [u8; 42],
     keys_pressed: u8,
     _unknown_1: [u8; 3],
@@ -223,33 +326,57 @@ struct MiscPacket {
     _unknown_2: [u8; 12],
 }
 
-unsafe impl bytemuck::Zeroable for MiscPacket {}
-unsafe impl bytemuck::Pod for MiscPacket {}
+impl MiscPacket {
+    /// Parse the packet after the type byte
+    fn parse(reader: &mut impl Read) -> Result<Self> {
+        Ok(Self {
+            _seq: reader.read_u32::<LittleEndian>()?,
+            _unknown_0: read_array(reader)?,
+            keys_pressed: reader.read_u8()?,
+            _unknown_1: read_array(reader)?,
+            proxy_sensor: reader.read_u8()?,
+            _unknown_2: read_array(reader)?,
+        })
+    }
+}
 
+// The sensor vectors in both packet types are already in the units GlassesEvent uses
+// (m/s^2, rad/s and uT), so they are passed through without scaling. A still, level
+// device reads about (0, 9.81, 0) on the accelerometer.
 #[derive(Debug, Clone, Copy)]
-#[repr(C, packed)]
 struct SensorPacket {
-    packet_type: u8,
     sensor_type: u8,
-    seq: u32,
+    _seq: u32,
     _unknown_0: [u8; 3],
     timestamp: u64,
     _unknown_1: [u8; 4],
-    vector: [f32; 3],
+    vector: Vector3<f32>,
+    // TODO: Magnetometer apparently gives an accuracy value too
     _unknown_2: [u8; 31],
 }
 
-unsafe impl bytemuck::Zeroable for SensorPacket {}
-unsafe impl bytemuck::Pod for SensorPacket {}
+impl SensorPacket {
+    /// Parse the packet after the type byte
+    fn parse(reader: &mut impl Read) -> Result<Self> {
+        Ok(Self {
+            sensor_type: reader.read_u8()?,
+            _seq: reader.read_u32::<LittleEndian>()?,
+            _unknown_0: read_array(reader)?,
+            timestamp: reader.read_u64::<LittleEndian>()?,
+            _unknown_1: read_array(reader)?,
+            vector: read_vector(reader)?,
+            _unknown_2: read_array(reader)?,
+        })
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
-#[repr(C, packed)]
 struct CombinedPacket {
-    packet_type: u8,
+    // In nanoseconds, unlike in SensorPacket, where it is in microseconds
     timestamp: u64,
-    accelerometer: [f32; 3],
-    gyroscope: [f32; 3],
-    magnetometer: [f32; 3],
+    accelerometer: Vector3<f32>,
+    gyroscope: Vector3<f32>,
+    magnetometer: Vector3<f32>,
     keys_pressed: u8,
     proxy_sensor: u8,
     _unknown_0: u8,
@@ -260,14 +387,51 @@ struct CombinedPacket {
     _unknown_2: [u8; 3],
 }
 
-unsafe impl bytemuck::Zeroable for CombinedPacket {}
-unsafe impl bytemuck::Pod for CombinedPacket {}
+impl CombinedPacket {
+    /// Parse the packet after the type byte
+    fn parse(reader: &mut impl Read) -> Result<Self> {
+        Ok(Self {
+            timestamp: reader.read_u64::<LittleEndian>()?,
+            accelerometer: read_vector(reader)?,
+            gyroscope: read_vector(reader)?,
+            magnetometer: read_vector(reader)?,
+            keys_pressed: reader.read_u8()?,
+            proxy_sensor: reader.read_u8()?,
+            _unknown_0: reader.read_u8()?,
+            vsync_timestamp: reader.read_u64::<LittleEndian>()?,
+            _unknown_1: read_array(reader)?,
+            display_brightness: reader.read_u8()?,
+            volume: reader.read_u8()?,
+            _unknown_2: read_array(reader)?,
+        })
+    }
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> Result<[u8; N]> {
+    let mut result = [0u8; N];
+    reader.read_exact(&mut result)?;
+    Ok(result)
+}
+
+fn read_vector(reader: &mut impl Read) -> Result<Vector3<f32>> {
+    Ok(Vector3::new(
+        reader.read_f32::<LittleEndian>()?,
+        reader.read_f32::<LittleEndian>()?,
+        reader.read_f32::<LittleEndian>()?,
+    ))
+}
 
 impl RokidAir {
     /// Vendor ID of the Rokid Air (Yes, it is 1234. Yes that's probably not very legit)
     pub const VID: u16 = 0x04d2;
     /// Product ID of the Rokid Air
     pub const PID: u16 = 0x162f;
+    /// Product ID of a variant that uses a different USB endpoint for the sensor data
+    pub const ALT_PID: u16 = 0x162d;
+    /// Default timeout of USB transfers. See [`RokidAir::set_timeout`]
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(250);
+    /// Maximum value accepted by [`RokidAir::set_brightness_raw`]
+    pub const MAX_BRIGHTNESS_RAW: u16 = 100;
 
     /// Connect to a specific glasses, based on the two USB fds
     /// Mainly made to work around android permission issues
@@ -287,50 +451,897 @@ impl RokidAir {
     pub fn new() -> Result<Self> {
         use crate::util::get_device_vid_pid;
 
-        Self::new_common(get_device_vid_pid(Self::VID, Self::PID)?.open()?)
+        let device = get_device_vid_pid(Self::VID, Self::PID)
+            .or_else(|_| get_device_vid_pid(Self::VID, Self::ALT_PID))?;
+        Self::new_common(open_device(&device)?)
+    }
+
+    /// The specific model of the connected glasses
+    pub fn model(&self) -> RokidModel {
+        self.model
+    }
+
+    /// USB bus, address, speed and claimed interface of the glasses, for diagnosing
+    /// flaky connections. Returns [`Error::NotImplemented`] if the glasses were created
+    /// with [`RokidAir::new_from_transport`].
+    pub fn connection_info(&self) -> Result<ConnectionInfo> {
+        self.connection_info.ok_or(Error::NotImplemented)
+    }
+
+    /// Reset the USB device, e.g. to recover glasses that stopped sending sensor data,
+    /// without unplugging them. The interface is claimed again afterwards, and all queued
+    /// events and partial sensor state are discarded. A [`GlassesEvent::ClockReset`] is
+    /// queued, since the device clock may restart.
+    ///
+    /// If the glasses re-enumerate during the reset, [`Error::Disconnected`] is returned,
+    /// and they have to be reopened (e.g. with [`RokidAir::new_by_serial`]). Fails while
+    /// a read started with `read_event_async` is still running.
+    ///
+    /// The glasses need some time to start up again, so reads right after the reset may
+    /// return [`Error::Timeout`]; these should simply be retried. The interrupt endpoint
+    /// does not need to be selected again: it only depends on the product ID, which can
+    /// only change if the device re-enumerates.
+    pub fn reset(&mut self) -> Result<()> {
+        Arc::get_mut(&mut self.transport)
+            .ok_or(Error::Other("The glasses are in use by another thread"))?
+            .reset()?;
+        self.pending_events.clear();
+        self.last_accelerometer = None;
+        self.last_gyroscope = None;
+        self.previous_key_states = 0;
+        self.last_timestamp = 0;
+        self.key_repeat_timestamps = [0; 8];
+        self.proxy_sensor_was_far = None;
+        self.proximity_change_count = 0;
+        self.last_reported_brightness = None;
+        self.last_reported_volume = None;
+        self.last_vsync_timestamp = 0;
+        self.pending_events.push_back(GlassesEvent::ClockReset);
+        Ok(())
+    }
+
+    /// List the connected Rokid glasses. Use [`RokidAir::new_from_info`] to connect to one of them.
+    /// See [`crate::enumeration::list_devices`] for the details.
+    #[cfg(not(target_os = "android"))]
+    pub fn list() -> Result<Vec<GlassesInfo>> {
+        Ok(crate::enumeration::list_devices()?
+            .into_iter()
+            .filter(|info| info.vendor_id == Self::VID)
+            .collect())
     }
 
-    fn new_common(mut device_handle: DeviceHandle<GlobalContext>) -> Result<Self> {
-        device_handle.set_auto_detach_kernel_driver(true)?;
+    /// Connect to the specific glasses described by `info`. See [`RokidAir::list`]
+    #[cfg(not(target_os = "android"))]
+    pub fn new_from_info(info: &GlassesInfo) -> Result<Self> {
+        use crate::util::get_device_bus_address;
 
-        device_handle.claim_interface(
-            get_interface_for_endpoint(&device_handle.device(), INTERRUPT_IN_ENDPOINT).ok_or_else(
-                || Error::Other("Could not find endpoint, wrong USB structure (probably)"),
-            )?,
+        if info.vendor_id != Self::VID
+            || (info.product_id != Self::PID && info.product_id != Self::ALT_PID)
+        {
+            return Err(Error::Other("Not a Rokid device"));
+        }
+        Self::new_common(open_device(&get_device_bus_address(
+            info.bus_number,
+            info.address,
+        )?)?)
+    }
+
+    /// A udev rule that gives every user access to Rokid glasses on Linux, one line per
+    /// product ID. Setup tools can write this to e.g. `/etc/udev/rules.d/70-rokid.rules`.
+    /// Without it, connecting fails with [`Error::PermissionDenied`] for non-root users.
+    pub fn required_udev_rule() -> String {
+        [Self::PID, Self::ALT_PID]
+            .map(|product_id| crate::udev_rule(Self::VID, product_id))
+            .join("\n")
+    }
+
+    /// Find the connected Rokid glasses with the given serial number, and connect to it.
+    /// Useful if multiple glasses are connected at the same time.
+    ///
+    /// Glasses that can't be opened or don't report their serial number are skipped. If
+    /// no match was found and some glasses could not be opened because of missing
+    /// permissions, [`Error::PermissionDenied`] is returned instead of [`Error::NotFound`].
+    #[cfg(not(target_os = "android"))]
+    pub fn new_by_serial(serial: &str) -> Result<Self> {
+        use crate::util::get_devices_vid_pid;
+
+        let mut found = None;
+        let mut permission_error = None;
+        let mut devices = get_devices_vid_pid(Self::VID, Self::PID)?;
+        devices.extend(get_devices_vid_pid(Self::VID, Self::ALT_PID)?);
+        for device in devices {
+            let device_handle = match open_device(&device) {
+                Ok(device_handle) => device_handle,
+                Err(e @ Error::PermissionDenied { .. }) => {
+                    permission_error.get_or_insert(e);
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            let Ok(device_serial) = Self::read_serial(&device_handle, Self::DEFAULT_TIMEOUT) else {
+                continue;
+            };
+            if device_serial == serial {
+                if found.is_some() {
+                    return Err(Error::Other("Multiple glasses have the same serial number"));
+                }
+                found = Some(device_handle);
+            }
+        }
+        Self::new_common(found.ok_or(permission_error.unwrap_or(Error::NotFound))?)
+    }
+
+    fn new_common(device_handle: DeviceHandle<GlobalContext>) -> Result<Self> {
+        let device_descriptor = device_handle.device().device_descriptor()?;
+        let endpoint = interrupt_endpoint(device_descriptor.product_id());
+        let interface = get_interface_for_endpoint(&device_handle.device(), endpoint).ok_or(
+            Error::Other("Interrupt endpoint not found, see enumeration::dump_usb_topology()"),
         )?;
-        let product_string = device_handle
-            .read_product_string_ascii(&device_handle.device().device_descriptor()?)?;
-        let result = Self {
-            device_handle,
+        let product_string = device_handle.read_product_string_ascii(&device_descriptor)?;
+        let transport = UsbTransport::new(device_handle, interface)?;
+        let connection_info = transport.connection_info();
+        let mut result = Self::new_from_parts(
+            Arc::new(transport),
+            RokidModel::from_product_string(&product_string),
+            endpoint,
+        );
+        result.connection_info = Some(connection_info);
+        Ok(result)
+    }
+
+    /// Create an instance that communicates through an arbitrary [`Transport`], e.g. a mock
+    /// for testing. The transport must behave like the glasses of the given `model`.
+    pub fn new_from_transport(transport: Box<dyn Transport>, model: RokidModel) -> Self {
+        Self::new_from_parts(transport.into(), model, INTERRUPT_IN_ENDPOINT)
+    }
+
+    fn new_from_parts(transport: Arc<dyn Transport>, model: RokidModel, endpoint: u8) -> Self {
+        Self {
+            transport,
+            endpoint,
+            timeout: Self::DEFAULT_TIMEOUT,
             last_accelerometer: None,
             last_gyroscope: None,
+            acc_gyro_tolerance: 0,
+            gyro_bias: Vector3::zeros(),
+            accelerometer_tolerance: Some(DEFAULT_ACCELEROMETER_TOLERANCE),
+            dropped_sample_count: 0,
+            imu_rate: None,
             previous_key_states: 0,
-            proxy_sensor_was_far: false,
-            model: if product_string.contains("Max") {
-                RokidModel::Max
-            } else {
-                RokidModel::Air
-            },
+            key_repeat_interval: None,
+            key_repeat_timestamps: [0; 8],
+            last_timestamp: 0,
+            proxy_sensor_was_far: None,
+            proximity_debounce: 1,
+            proximity_change_count: 0,
+            last_reported_brightness: None,
+            last_reported_volume: None,
+            last_vsync_timestamp: 0,
+            model,
             pending_events: Default::default(),
+            connection_info: None,
+            raw_packet_sink: None,
+        }
+    }
+
+    /// Get a single sensor event, waiting at most `timeout` for new data from the device.
+    /// Already queued events are returned without touching USB. Packets that do not
+    /// produce an event on their own (e.g. an accelerometer sample waiting for its
+    /// gyroscope pair) are read until the timeout is over.
+    ///
+    /// Returns `Ok(None)` if no complete event arrived in time, so this can be called once
+    /// per frame from a main loop. Timeouts shorter than a millisecond (including zero,
+    /// which would mean "wait forever" in libusb) are rounded up to a millisecond.
+    /// Real USB failures are still returned as errors, e.g. [`Error::Disconnected`] if the
+    /// glasses were unplugged.
+    pub fn read_event_timeout(&mut self, timeout: Duration) -> Result<Option<GlassesEvent>> {
+        let deadline = Instant::now() + timeout;
+        while self.pending_events.is_empty() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let mut packet_data = [0u8; 0x40];
+            match self.transport.read_interrupt(
+                self.endpoint,
+                &mut packet_data,
+                remaining.max(MIN_READ_TIMEOUT),
+            ) {
+                Ok(length) => self.handle_packet(&packet_data, length),
+                Err(Error::Timeout) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
+        Ok(self.pop_event())
+    }
+
+    /// Get a single sensor event, without blocking the async runtime.
+    /// Already queued events are returned without touching USB.
+    ///
+    /// The blocking USB read is done on tokio's blocking thread pool, so this must be
+    /// called from within a tokio runtime. Only one read may be in flight at a time: if the
+    /// returned future is dropped before completion, the packet it was waiting for is lost.
+    #[cfg(feature = "async")]
+    pub async fn read_event_async(&mut self) -> Result<GlassesEvent> {
+        while self.pending_events.is_empty() {
+            let transport = self.transport.clone();
+            let endpoint = self.endpoint;
+            let timeout = self.timeout;
+            let (packet_data, length) = tokio::task::spawn_blocking(move || {
+                let mut packet_data = [0u8; 0x40];
+                let length = transport.read_interrupt(endpoint, &mut packet_data, timeout)?;
+                Ok::<_, Error>((packet_data, length))
+            })
+            .await
+            .map_err(|_| Error::Other("Blocking read task failed"))??;
+            self.handle_packet(&packet_data, length);
+        }
+        Ok(self.pop_event().unwrap())
+    }
+
+    /// Set the gyroscope bias, which is subtracted from the gyroscope data of every
+    /// [`GlassesEvent::AccGyro`] event. Unit is rad/sec. Zero by default.
+    ///
+    /// See [`RokidAir::estimate_gyro_bias`] for a way to measure it.
+    pub fn set_gyro_bias(&mut self, bias: Vector3<f32>) {
+        self.gyro_bias = bias;
+    }
+
+    /// The gyroscope bias set by [`RokidAir::set_gyro_bias`]
+    pub fn gyro_bias(&self) -> Vector3<f32> {
+        self.gyro_bias
+    }
+
+    /// Estimate the gyroscope bias by averaging the raw gyroscope data of the next
+    /// `samples` [`GlassesEvent::AccGyro`] events. The result can be passed to
+    /// [`RokidAir::set_gyro_bias`]; it is not applied automatically.
+    ///
+    /// The glasses must be held completely still during the estimation (e.g. lying on a
+    /// table). All events read in the meantime are discarded. If the accelerometer shows
+    /// that the glasses were moved, an error is returned.
+    pub fn estimate_gyro_bias(&mut self, samples: usize) -> Result<Vector3<f32>> {
+        if samples == 0 {
+            return Err(Error::Other("At least one sample is needed"));
+        }
+        // Accumulated in f64, with Welford's algorithm for the variance, so that the
+        // results stay accurate even for a lot of samples
+        let mut gyroscope_sum = Vector3::<f64>::zeros();
+        let mut accelerometer_mean = Vector3::<f64>::zeros();
+        let mut accelerometer_square_deviation_sum = 0.0;
+        let mut count = 0;
+        while count < samples {
+            if let GlassesEvent::AccGyro {
+                accelerometer,
+                gyroscope,
+                ..
+            } = self.read_event()?
+            {
+                count += 1;
+                gyroscope_sum += (gyroscope + self.gyro_bias).cast::<f64>();
+                let accelerometer = accelerometer.cast::<f64>();
+                let delta = accelerometer - accelerometer_mean;
+                accelerometer_mean += delta / count as f64;
+                accelerometer_square_deviation_sum +=
+                    delta.dot(&(accelerometer - accelerometer_mean));
+            }
+        }
+        let samples = samples as f64;
+        let accelerometer_variance = accelerometer_square_deviation_sum / samples;
+        if accelerometer_variance > MAX_STILL_ACCELEROMETER_VARIANCE {
+            return Err(Error::Other(
+                "Glasses were moved during gyro bias estimation",
+            ));
+        }
+        Ok((gyroscope_sum / samples).cast::<f32>())
+    }
+
+    /// Set how far apart the timestamps of an accelerometer and a gyroscope sample may be
+    /// to still be sent together as a [`GlassesEvent::AccGyro`] (with the accelerometer's
+    /// timestamp). Samples that can't be paired are sent as separate
+    /// [`GlassesEvent::Accelerometer`] and [`GlassesEvent::Gyroscope`] events.
+    ///
+    /// The default is zero, i.e. the timestamps must be equal. Only matters for glasses
+    /// that send the two sensors in separate packets.
+    pub fn set_acc_gyro_tolerance(&mut self, tolerance: Duration) {
+        self.acc_gyro_tolerance = tolerance.as_micros() as u64;
+    }
+
+    /// Set how far the magnitude of an accelerometer sample may be from 1g (in either
+    /// direction, as a factor) before the sample is considered corrupted and dropped.
+    /// The default is 10, `None` disables the check.
+    ///
+    /// Samples with NaN or infinite components are always dropped.
+    /// See [`RokidAir::dropped_sample_count`].
+    pub fn set_accelerometer_tolerance(&mut self, tolerance: Option<f32>) {
+        self.accelerometer_tolerance = tolerance;
+    }
+
+    /// Number of sensor samples dropped because they looked corrupted, since connecting
+    pub fn dropped_sample_count(&self) -> u64 {
+        self.dropped_sample_count
+    }
+
+    /// Set the timeout of all USB transfers, both control requests and sensor data reads.
+    /// Default is [`RokidAir::DEFAULT_TIMEOUT`]. Increase it if control requests fail with
+    /// [`Error::Timeout`] (e.g. behind some USB hubs or in VMs).
+    ///
+    /// Note that [`ARGlasses::read_event`] may block for this long if no data arrives,
+    /// so a large timeout will also delay noticing problems with the connection.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Measure the rate of [`GlassesEvent::AccGyro`] events (in samples per second) by
+    /// counting them for `duration`. The result is also stored, see [`RokidAir::imu_rate`].
+    ///
+    /// The events read during the measurement are not lost: they are queued, and
+    /// returned by the following [`ARGlasses::read_event`] calls.
+    pub fn measure_imu_rate(&mut self, duration: Duration) -> Result<f32> {
+        let mut events = VecDeque::new();
+        let mut count = 0;
+        let start = Instant::now();
+        let result = loop {
+            if start.elapsed() >= duration {
+                break Ok(());
+            }
+            match self.read_event() {
+                Ok(event) => {
+                    if let GlassesEvent::AccGyro { .. } = event {
+                        count += 1;
+                    }
+                    events.push_back(event);
+                }
+                Err(e) => break Err(e),
+            }
         };
+        let rate = count as f32 / start.elapsed().as_secs_f32();
+        events.append(&mut self.pending_events);
+        self.pending_events = events;
+        result?;
+        self.imu_rate = Some(rate);
+        Ok(rate)
+    }
+
+    /// The result of the last [`RokidAir::measure_imu_rate`] call
+    pub fn imu_rate(&self) -> Option<f32> {
+        self.imu_rate
+    }
+
+    /// Enable or disable key auto-repeat. If enabled, [`GlassesEvent::KeyRepeat`] is sent
+    /// every `interval` while a key is held down, the first one `interval` after the
+    /// [`GlassesEvent::KeyPress`]. Disabled by default.
+    ///
+    /// The timing is based on the device timestamps of the sensor packets.
+    pub fn set_key_repeat(&mut self, interval: Option<Duration>) {
+        self.key_repeat_interval = interval;
+        self.key_repeat_timestamps = [self.last_timestamp; 8];
+    }
+
+    /// Only send [`GlassesEvent::ProximityNear`] and [`GlassesEvent::ProximityFar`] after
+    /// the proximity sensor reported the new state in `packets` consecutive packets.
+    /// This suppresses event storms from a sensor flickering at its threshold.
+    ///
+    /// The default is 1, i.e. every change is reported immediately. 0 is treated as 1.
+    pub fn set_proximity_debounce(&mut self, packets: u8) {
+        self.proximity_debounce = packets.max(1);
+        self.proximity_change_count = 0;
+    }
+
+    /// Whether the proximity sensor currently senses the user, i.e. the glasses are worn.
+    ///
+    /// This is the state of the last [`GlassesEvent::ProximityNear`] or
+    /// [`GlassesEvent::ProximityFar`] event, or the initial state if there was no change
+    /// yet. The initial state is not sent as an event. Right after connecting, packets are
+    /// read until one with proximity data arrives; other events read in the meantime are
+    /// queued for [`ARGlasses::read_event`].
+    /// Returns [`Error::Timeout`] if there was no proximity data for a second.
+    pub fn proximity_state(&mut self) -> Result<bool> {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        loop {
+            if let Some(proxy_sensor_was_far) = self.proxy_sensor_was_far {
+                return Ok(!proxy_sensor_was_far);
+            }
+            if Instant::now() > deadline {
+                return Err(Error::Timeout);
+            }
+            let mut packet_data = [0u8; 0x40];
+            match self
+                .transport
+                .read_interrupt(self.endpoint, &mut packet_data, self.timeout)
+            {
+                Ok(length) => self.handle_packet(&packet_data, length),
+                // Checked against the deadline above
+                Err(Error::Timeout) => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Read the next packet from the glasses, without parsing it. Bytes after the end of
+    /// short packets are zero.
+    ///
+    /// This reads from the same stream as [`ARGlasses::read_event`]: a packet returned
+    /// here is never turned into events, and already queued events stay queued. To see
+    /// the packets while still getting the events, use [`RokidAir::set_raw_packet_sink`].
+    pub fn read_raw_packet(&mut self) -> Result<[u8; 0x40]> {
+        let mut packet_data = [0u8; 0x40];
+        self.transport
+            .read_interrupt(self.endpoint, &mut packet_data, self.timeout)?;
+        Ok(packet_data)
+    }
+
+    /// Send a copy of every packet read from the glasses to `sink`, before it is parsed.
+    /// This includes packet types that are otherwise ignored, so it is useful for reverse
+    /// engineering the protocol. Bytes after the end of short packets are zero.
+    ///
+    /// The sink is removed automatically when its receiver is dropped. `None` disables it.
+    pub fn set_raw_packet_sink(&mut self, sink: Option<Sender<[u8; 0x40]>>) {
+        self.raw_packet_sink = sink;
+    }
+
+    /// Move the glasses to a background thread that continuously reads events, and
+    /// sends them through a channel.
+    ///
+    /// This consumes the glasses, since the USB handle can't be shared between threads.
+    /// The thread exits when the receiver is dropped, or after sending a fatal error. Timeouts
+    /// ([`Error::Timeout`] and [`Error::PacketTimeout`]) are sent too, but reading goes on,
+    /// just like with [`crate::ARGlassesExt::events`].
+    pub fn spawn_event_thread(mut self) -> (JoinHandle<()>, Receiver<Result<GlassesEvent>>) {
+        let (sender, receiver) = channel();
+        let join_handle = std::thread::spawn(move || loop {
+            let result = self.read_event();
+            let fatal = matches!(result, Err(ref e) if !e.is_transient());
+            if sender.send(result).is_err() || fatal {
+                break;
+            }
+        });
+        (join_handle, receiver)
+    }
+
+    /// Get the display brightness as a level between 1 and 6.
+    /// This is the same scale [`RokidAir::set_brightness`] uses.
+    ///
+    /// The device reports the raw brightness percentage in byte 1 of the control response.
+    pub fn brightness(&mut self) -> Result<u8> {
+        let result = self.read_value(0x82, 0x0, 0x2, 2)?;
+        BRIGHTNESS_LEVELS
+            .iter()
+            .position(|percent| *percent == result[1])
+            .map(|level| level as u8 + 1)
+            .ok_or(Error::Other("Unknown brightness value"))
+    }
+
+    /// Get the display brightness as a level between 1 and 6, like [`RokidAir::brightness`].
+    ///
+    /// As opposed to [`RokidAir::brightness`], this does not fail if the device reports a
+    /// percentage that is not one of the levels (e.g. because it was set by another app).
+    /// Instead it returns the level closest to the actual brightness.
+    pub fn get_brightness_level(&mut self) -> Result<u8> {
+        let result = self.read_value(0x82, 0x0, 0x2, 2)?;
+        let level = BRIGHTNESS_LEVELS
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, percent)| percent.abs_diff(result[1]))
+            .map(|(level, _)| level as u8 + 1)
+            .unwrap();
+        Ok(level)
+    }
+
+    /// Set the display brightness. Allowed values are between 1-6, which correspond to
+    /// 10, 30, 45, 60, 80 and 100 percent.
+    pub fn set_brightness(&mut self, brightness: u8) -> Result<()> {
+        let percent = match brightness {
+            1..=6 => BRIGHTNESS_LEVELS[brightness as usize - 1],
+            _ => return Err(Error::Other("Brightness level out of range")),
+        };
+        self.set_brightness_percent(percent)
+    }
+
+    /// Set the display brightness directly, as a percentage between 1-100.
+    /// Allows finer control than [`RokidAir::set_brightness`].
+    pub fn set_brightness_percent(&mut self, percent: u8) -> Result<()> {
+        if !(1..=100).contains(&percent) {
+            return Err(Error::Other("Brightness percentage out of range"));
+        }
+        self.set_brightness_raw(percent as u16)
+    }
+
+    /// Get the raw display mode number, as reported by the device. The values 0-5 are the
+    /// modes documented by the official CLI: 0: [`DisplayMode::SameOnBoth`],
+    /// 1: [`DisplayMode::Stereo`], 2: [`DisplayMode::HalfSBS`],
+    /// 3: [`DisplayMode::HighRefreshRate`], 4: [`DisplayMode::HighRefreshRateSBS`], and 5,
+    /// which is never set by this library, and is reported as
+    /// [`DisplayMode::HighRefreshRate`] by [`ARGlasses::get_display_mode`].
+    /// Useful for telling 3 and 5 apart, and for seeing modes that
+    /// [`ARGlasses::get_display_mode`] does not know about (it returns an error for them).
+    pub fn raw_display_mode(&mut self) -> Result<u8> {
+        let result = self.read_value(0x81, 0x0, 0x1, 2)?;
+        Ok(result[1])
+    }
+
+    /// Get the raw display brightness value, as reported by the device.
+    /// This is a percentage, where the values used by the official software are
+    /// the ones in [`RokidAir::set_brightness`].
+    pub fn brightness_raw(&mut self) -> Result<u16> {
+        let result = self.read_value(0x82, 0x0, 0x2, 2)?;
+        Ok(result[1] as u16)
+    }
+
+    /// Write a raw display brightness value, clamped to [`RokidAir::MAX_BRIGHTNESS_RAW`].
+    /// Unlike [`RokidAir::set_brightness_percent`], 0 is not rejected.
+    pub fn set_brightness_raw(&mut self, value: u16) -> Result<()> {
+        self.write_value(0x2, value.min(Self::MAX_BRIGHTNESS_RAW), 0x2, &[0u8; 1])
+    }
+
+    /// The display brightness most recently reported in the sensor data stream, in the raw
+    /// units of the glasses. This does not need a separate request to the glasses.
+    ///
+    /// Only available on models that send combined sensor packets (e.g. the Max), and
+    /// only after the first packet was read. `None` otherwise.
+    pub fn last_reported_brightness(&self) -> Option<u8> {
+        self.last_reported_brightness
+    }
+
+    /// The volume most recently reported in the sensor data stream, in the raw units of the
+    /// glasses. See [`RokidAir::last_reported_brightness`] for availability.
+    pub fn last_reported_volume(&self) -> Option<u8> {
+        self.last_reported_volume
+    }
+
+    /// Get the audio volume, as a level between 0 and 10.
+    /// This is the same scale [`RokidAir::set_volume`] uses.
+    ///
+    /// The device reports the raw volume (0-100) in byte 1 of the control response,
+    /// the same place where the display mode is returned.
+    pub fn volume(&mut self) -> Result<u8> {
+        let result = self.read_value(0x81, 0x0, 0xa, 2)?;
+        match result[1] {
+            raw @ 0..=100 if raw % 10 == 0 => Ok(raw / 10),
+            _ => Err(Error::Other("Unknown volume value")),
+        }
+    }
+
+    /// Get the audio volume as a level between 0 and 10, like [`RokidAir::volume`].
+    ///
+    /// As opposed to [`RokidAir::volume`], this does not fail if the device reports a raw
+    /// volume that is not one of the levels (e.g. because it was set by another app).
+    /// Instead it returns the level below the actual volume.
+    pub fn get_volume_level(&mut self) -> Result<u8> {
+        let result = self.read_value(0x81, 0x0, 0xa, 2)?;
+        Ok(result[1] / 10)
+    }
+
+    /// Set the audio volume. Allowed values are between 0-10
+    pub fn set_volume(&mut self, volume: u8) -> Result<()> {
+        if volume > 10 {
+            return Err(Error::Other("Volume out of range"));
+        }
+        self.write_value(0x1, volume as u16 * 10, 0xa, &[0u8; 1])
+    }
+
+    /// The keys that were held down according to the last received packet.
+    /// Bit `n` is set if the key reported as [`GlassesEvent::KeyPress`]`(n)` is held down.
+    pub fn key_states(&self) -> u8 {
+        self.previous_key_states
+    }
+
+    /// Get the key mask of the glasses, i.e. which hardware keys are handled by the
+    /// glasses themselves. Bit `n` corresponds to the key reported as
+    /// [`GlassesEvent::KeyPress`]`(n)`.
+    pub fn get_keymask(&mut self) -> Result<u8> {
+        let result = self.read_value(0x81, 0x3200, 0x0, 1)?;
+        Ok(result[0])
+    }
+
+    /// Set the key mask of the glasses. See [`RokidAir::get_keymask`] for the bit layout.
+    /// Clearing a bit disables the glasses' own handling of that key, so that the
+    /// application can use it exclusively.
+    pub fn set_keymask(&mut self, mask: u8) -> Result<()> {
+        self.write_value(0x1, 0x3200, 0x0, &[mask])
+    }
+
+    pub(crate) fn read_serial(transport: &dyn Transport, timeout: Duration) -> Result<String> {
+        let mut result = [0u8; 0x40];
+        transport.read_control(0x81, 0x100, 0, &mut result, timeout)?;
+        Ok(
+            String::from_utf8(result.iter().copied().take_while(|c| *c != 0).collect())
+                .map_err(|_| "Invalid serial string")?,
+        )
+    }
+
+    /// Read a control value. The response must be at least `min_length` bytes long.
+    fn read_value(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        min_length: usize,
+    ) -> Result<[u8; 0x40]> {
+        let mut result = [0u8; 0x40];
+        let received =
+            self.transport
+                .read_control(request, value, index, &mut result, self.timeout)?;
+        if received < min_length {
+            return Err(Error::ShortRead {
+                expected: min_length,
+                got: received,
+            });
+        }
         Ok(result)
     }
 
+    fn write_value(&self, request: u8, value: u16, index: u16, data: &[u8]) -> Result<()> {
+        self.transport
+            .write_control(request, value, index, data, self.timeout)?;
+        Ok(())
+    }
+
+    fn handle_packet(&mut self, packet_data: &[u8; 0x40], length: usize) {
+        if let Some(sink) = &self.raw_packet_sink {
+            if sink.send(*packet_data).is_err() {
+                // The receiver was dropped
+                self.raw_packet_sink = None;
+            }
+        }
+        let Some((&packet_type, mut reader)) = packet_data[..length].split_first() else {
+            return;
+        };
+        match packet_type {
+            2 => {
+                let Ok(packet) = MiscPacket::parse(&mut reader) else {
+                    warn!("Dropped incomplete packet of type {packet_type} ({length} bytes)");
+                    return;
+                };
+                trace!("{packet:?}");
+                self.handle_key_press(packet.keys_pressed);
+                self.handle_proxy_sensor(packet.proxy_sensor);
+            }
+            4 => {
+                let Ok(packet) = SensorPacket::parse(&mut reader) else {
+                    warn!("Dropped incomplete packet of type {packet_type} ({length} bytes)");
+                    return;
+                };
+                trace!("{packet:?}");
+                let sensor_data = packet.vector;
+                self.update_timestamp(packet.timestamp);
+                let valid = match packet.sensor_type {
+                    1 => self.is_valid_accelerometer(&sensor_data),
+                    _ => is_valid_vector(&sensor_data),
+                };
+                if !valid {
+                    self.drop_sample();
+                    return;
+                }
+                match packet.sensor_type {
+                    1 => {
+                        let sample = Some((sensor_data, packet.timestamp));
+                        if let Some((accelerometer, timestamp)) =
+                            std::mem::replace(&mut self.last_accelerometer, sample)
+                        {
+                            self.pending_events.push_back(GlassesEvent::Accelerometer {
+                                accelerometer,
+                                timestamp,
+                            });
+                        }
+                    }
+                    2 => {
+                        let sample = Some((sensor_data, packet.timestamp));
+                        if let Some((gyroscope, timestamp)) =
+                            std::mem::replace(&mut self.last_gyroscope, sample)
+                        {
+                            self.pending_events.push_back(GlassesEvent::Gyroscope {
+                                gyroscope: gyroscope - self.gyro_bias,
+                                timestamp,
+                            });
+                        }
+                    }
+                    3 => self.pending_events.push_back(GlassesEvent::Magnetometer {
+                        magnetometer: sensor_data,
+                        timestamp: packet.timestamp,
+                    }),
+                    _ => (),
+                }
+                self.pair_acc_gyro();
+            }
+            17 => {
+                let Ok(packet) = CombinedPacket::parse(&mut reader) else {
+                    warn!("Dropped incomplete packet of type {packet_type} ({length} bytes)");
+                    return;
+                };
+                trace!("{packet:?}");
+                let timestamp = packet.timestamp / 1000;
+                self.update_timestamp(timestamp);
+                let CombinedPacket {
+                    accelerometer,
+                    gyroscope,
+                    magnetometer,
+                    ..
+                } = packet;
+                if self.is_valid_accelerometer(&accelerometer) && is_valid_vector(&gyroscope) {
+                    self.pending_events.push_back(GlassesEvent::AccGyro {
+                        accelerometer,
+                        gyroscope: gyroscope - self.gyro_bias,
+                        timestamp,
+                    });
+                } else {
+                    self.drop_sample();
+                }
+                if is_valid_vector(&magnetometer) {
+                    self.pending_events.push_back(GlassesEvent::Magnetometer {
+                        magnetometer,
+                        timestamp,
+                    });
+                } else {
+                    self.drop_sample();
+                }
+                // NOTE: was always zero on my Max
+                self.handle_key_press(packet.keys_pressed);
+                self.handle_proxy_sensor(packet.proxy_sensor);
+                self.handle_brightness_volume(packet.display_brightness, packet.volume);
+                // Nanoseconds, same clock as the sensor timestamp. Only report new values,
+                // so that a v-sync is not sent with every sensor packet.
+                let vsync_timestamp = packet.vsync_timestamp;
+                if vsync_timestamp != 0 && vsync_timestamp != self.last_vsync_timestamp {
+                    self.last_vsync_timestamp = vsync_timestamp;
+                    self.pending_events.push_back(GlassesEvent::VSync {
+                        timestamp: Some(vsync_timestamp / 1000),
+                    });
+                }
+            }
+            _ => {}
+        }
+        self.handle_key_repeat();
+    }
+
+    /// Take the next queued event. Every event returned to the user goes through here.
+    fn pop_event(&mut self) -> Option<GlassesEvent> {
+        let event = self.pending_events.pop_front()?;
+        debug!("{event:?}");
+        Some(event)
+    }
+
+    fn drop_sample(&mut self) {
+        warn!("Dropped corrupted sensor sample");
+        self.dropped_sample_count += 1;
+    }
+
+    /// Emit the buffered accelerometer and gyroscope samples as a single
+    /// [`GlassesEvent::AccGyro`] if their timestamps match. Otherwise the older one can
+    /// never be paired (samples arrive in order), so it is emitted on its own.
+    fn pair_acc_gyro(&mut self) {
+        let (Some((accelerometer, acc_ts)), Some((gyroscope, gyro_ts))) =
+            (self.last_accelerometer, self.last_gyroscope)
+        else {
+            return;
+        };
+        if acc_ts.abs_diff(gyro_ts) <= self.acc_gyro_tolerance {
+            self.last_gyroscope = None;
+            self.last_accelerometer = None;
+            self.pending_events.push_back(GlassesEvent::AccGyro {
+                accelerometer,
+                gyroscope: gyroscope - self.gyro_bias,
+                timestamp: acc_ts,
+            });
+        } else if acc_ts < gyro_ts {
+            self.last_accelerometer = None;
+            self.pending_events.push_back(GlassesEvent::Accelerometer {
+                accelerometer,
+                timestamp: acc_ts,
+            });
+        } else {
+            self.last_gyroscope = None;
+            self.pending_events.push_back(GlassesEvent::Gyroscope {
+                gyroscope: gyroscope - self.gyro_bias,
+                timestamp: gyro_ts,
+            });
+        }
+    }
+
+    fn is_valid_accelerometer(&self, accelerometer: &Vector3<f32>) -> bool {
+        if !is_valid_vector(accelerometer) {
+            return false;
+        }
+        let Some(tolerance) = self.accelerometer_tolerance else {
+            return true;
+        };
+        let magnitude = accelerometer.norm();
+        magnitude <= STANDARD_GRAVITY * tolerance && magnitude >= STANDARD_GRAVITY / tolerance
+    }
+
     fn handle_key_press(&mut self, keys_pressed: u8) {
         let new_presses = keys_pressed & !self.previous_key_states;
+        let new_releases = !keys_pressed & self.previous_key_states;
         for bit in 0..8 {
             if new_presses & (1 << bit) != 0 {
+                self.key_repeat_timestamps[bit as usize] = self.last_timestamp;
                 self.pending_events.push_back(GlassesEvent::KeyPress(bit))
             }
+            if new_releases & (1 << bit) != 0 {
+                self.pending_events.push_back(GlassesEvent::KeyRelease(bit))
+            }
         }
         self.previous_key_states = keys_pressed;
     }
 
+    fn handle_key_repeat(&mut self) {
+        let Some(interval) = self.key_repeat_interval else {
+            return;
+        };
+        let interval = u64::try_from(interval.as_micros()).unwrap_or(u64::MAX);
+        for bit in 0..8 {
+            let last_event = &mut self.key_repeat_timestamps[bit as usize];
+            if self.previous_key_states & (1 << bit) != 0
+                && self.last_timestamp >= last_event.saturating_add(interval)
+            {
+                *last_event = self.last_timestamp;
+                self.pending_events.push_back(GlassesEvent::KeyRepeat(bit))
+            }
+        }
+    }
+
+    fn handle_brightness_volume(&mut self, brightness: u8, volume: u8) {
+        if self
+            .last_reported_brightness
+            .replace(brightness)
+            .is_some_and(|b| b != brightness)
+        {
+            self.pending_events
+                .push_back(GlassesEvent::BrightnessChanged(brightness));
+        }
+        if self
+            .last_reported_volume
+            .replace(volume)
+            .is_some_and(|v| v != volume)
+        {
+            self.pending_events
+                .push_back(GlassesEvent::VolumeChanged(volume));
+        }
+    }
+
+    /// Track the device clock, and reset the time based state if it jumped backwards.
+    /// Buffered samples are sent unpaired before the [`GlassesEvent::ClockReset`].
+    fn update_timestamp(&mut self, timestamp: u64) {
+        if timestamp.saturating_add(CLOCK_RESET_THRESHOLD) < self.last_timestamp {
+            self.flush_acc_gyro();
+            self.key_repeat_timestamps = [timestamp; 8];
+            self.pending_events.push_back(GlassesEvent::ClockReset);
+            self.last_timestamp = timestamp;
+        } else {
+            self.last_timestamp = self.last_timestamp.max(timestamp);
+        }
+    }
+
+    /// Send the buffered accelerometer and gyroscope samples as unpaired events
+    fn flush_acc_gyro(&mut self) {
+        if let Some((accelerometer, timestamp)) = self.last_accelerometer.take() {
+            self.pending_events.push_back(GlassesEvent::Accelerometer {
+                accelerometer,
+                timestamp,
+            });
+        }
+        if let Some((gyroscope, timestamp)) = self.last_gyroscope.take() {
+            self.pending_events.push_back(GlassesEvent::Gyroscope {
+                gyroscope: gyroscope - self.gyro_bias,
+                timestamp,
+            });
+        }
+    }
+
     fn handle_proxy_sensor(&mut self, value: u8) {
         let proxy_sensor_is_far = value != 0;
-        let send_proxy_event = proxy_sensor_is_far != self.proxy_sensor_was_far;
-        self.proxy_sensor_was_far = proxy_sensor_is_far;
-        if send_proxy_event {
+        let Some(proxy_sensor_was_far) = self.proxy_sensor_was_far else {
+            // The initial state is not a change, so no event is sent for it
+            self.proxy_sensor_was_far = Some(proxy_sensor_is_far);
+            return;
+        };
+        if proxy_sensor_is_far == proxy_sensor_was_far {
+            self.proximity_change_count = 0;
+            return;
+        }
+        self.proximity_change_count += 1;
+        if self.proximity_change_count >= self.proximity_debounce {
+            self.proxy_sensor_was_far = Some(proxy_sensor_is_far);
+            self.proximity_change_count = 0;
             self.pending_events.push_back(if proxy_sensor_is_far {
                 GlassesEvent::ProximityFar
             } else {
@@ -339,3 +1350,626 @@ impl RokidAir {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+
+    const GRAVITY: Vector3<f32> = Vector3::new(0.0, STANDARD_GRAVITY, 0.0);
+
+    fn glasses(packets: &[Vec<u8>]) -> (RokidAir, Arc<MockTransport>) {
+        let transport = Arc::new(MockTransport::new());
+        for packet in packets {
+            transport.push_interrupt_packet(INTERRUPT_IN_ENDPOINT, packet);
+        }
+        let glasses = RokidAir::new_from_transport(Box::new(transport.clone()), RokidModel::Air);
+        (glasses, transport)
+    }
+
+    /// Read events until the scripted packets run out
+    fn read_all(glasses: &mut RokidAir) -> Vec<GlassesEvent> {
+        let mut result = Vec::new();
+        loop {
+            match glasses.read_event() {
+                Ok(event) => result.push(event),
+                Err(Error::Timeout) => return result,
+                Err(e) => panic!("Unexpected error: {e}"),
+            }
+        }
+    }
+
+    fn push_vector(packet: &mut Vec<u8>, vector: Vector3<f32>) {
+        for component in vector.iter() {
+            packet.extend(component.to_le_bytes());
+        }
+    }
+
+    fn misc_packet(keys_pressed: u8, proxy_sensor: u8) -> Vec<u8> {
+        let mut packet = vec![2];
+        packet.extend([0; 46]);
+        packet.push(keys_pressed);
+        packet.extend([0; 3]);
+        packet.push(proxy_sensor);
+        packet.resize(0x40, 0);
+        packet
+    }
+
+    fn sensor_packet(sensor_type: u8, timestamp: u64, vector: Vector3<f32>) -> Vec<u8> {
+        let mut packet = vec![4, sensor_type];
+        packet.extend([0; 7]);
+        packet.extend(timestamp.to_le_bytes());
+        packet.extend([0; 4]);
+        push_vector(&mut packet, vector);
+        packet.resize(0x40, 0);
+        packet
+    }
+
+    fn combined_packet(
+        timestamp_ns: u64,
+        accelerometer: Vector3<f32>,
+        gyroscope: Vector3<f32>,
+        magnetometer: Vector3<f32>,
+    ) -> Vec<u8> {
+        let mut packet = vec![17];
+        packet.extend(timestamp_ns.to_le_bytes());
+        push_vector(&mut packet, accelerometer);
+        push_vector(&mut packet, gyroscope);
+        push_vector(&mut packet, magnetometer);
+        packet.resize(0x40, 0);
+        packet
+    }
+
+    #[test]
+    fn misc_packet_keys() {
+        let (mut glasses, _) = glasses(&[misc_packet(0, 1), misc_packet(2, 1), misc_packet(0, 1)]);
+        let events = read_all(&mut glasses);
+        assert!(matches!(
+            events[..],
+            [GlassesEvent::KeyPress(1), GlassesEvent::KeyRelease(1)]
+        ));
+    }
+
+    #[test]
+    fn misc_packet_proximity() {
+        let (mut glasses, _) = glasses(&[misc_packet(0, 1), misc_packet(0, 0), misc_packet(0, 1)]);
+        let events = read_all(&mut glasses);
+        assert!(matches!(
+            events[..],
+            [GlassesEvent::ProximityNear, GlassesEvent::ProximityFar]
+        ));
+    }
+
+    #[test]
+    fn sensor_packets_are_paired() {
+        let gyroscope = Vector3::new(0.1, 0.2, 0.3);
+        let magnetometer = Vector3::new(10.0, 20.0, 30.0);
+        let (mut glasses, _) = glasses(&[
+            sensor_packet(1, 1000, GRAVITY),
+            sensor_packet(2, 1000, gyroscope),
+            sensor_packet(3, 1000, magnetometer),
+        ]);
+        let events = read_all(&mut glasses);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[0],
+            GlassesEvent::AccGyro { accelerometer: a, gyroscope: g, timestamp: 1000 }
+                if a == GRAVITY && g == gyroscope
+        ));
+        assert!(matches!(
+            events[1],
+            GlassesEvent::Magnetometer { magnetometer: m, timestamp: 1000 }
+                if m == magnetometer
+        ));
+    }
+
+    #[test]
+    fn combined_packet_events() {
+        let gyroscope = Vector3::new(0.1, 0.2, 0.3);
+        let magnetometer = Vector3::new(10.0, 20.0, 30.0);
+        let (mut glasses, _) =
+            glasses(&[combined_packet(2_000_000, GRAVITY, gyroscope, magnetometer)]);
+        let events = read_all(&mut glasses);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[0],
+            GlassesEvent::AccGyro { accelerometer: a, gyroscope: g, timestamp: 2000 }
+                if a == GRAVITY && g == gyroscope
+        ));
+        assert!(matches!(
+            events[1],
+            GlassesEvent::Magnetometer { magnetometer: m, timestamp: 2000 }
+                if m == magnetometer
+        ));
+    }
+
+    #[test]
+    fn out_of_order_samples_are_paired() {
+        let gyroscope = Vector3::new(0.1, 0.2, 0.3);
+        let (mut glasses, _) = glasses(&[
+            sensor_packet(1, 1001, GRAVITY),
+            sensor_packet(2, 1000, gyroscope),
+        ]);
+        glasses.set_acc_gyro_tolerance(Duration::from_micros(5));
+        let events = read_all(&mut glasses);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            GlassesEvent::AccGyro { accelerometer: a, gyroscope: g, timestamp: 1001 }
+                if a == GRAVITY && g == gyroscope
+        ));
+    }
+
+    #[test]
+    fn earlier_magnetometer_keeps_pending_sample() {
+        let gyroscope = Vector3::new(0.1, 0.2, 0.3);
+        let (mut glasses, _) = glasses(&[
+            sensor_packet(1, 1000, GRAVITY),
+            sensor_packet(3, 990, Vector3::new(10.0, 20.0, 30.0)),
+            sensor_packet(2, 1000, gyroscope),
+        ]);
+        let events = read_all(&mut glasses);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[0],
+            GlassesEvent::Magnetometer { timestamp: 990, .. }
+        ));
+        assert!(matches!(
+            events[1],
+            GlassesEvent::AccGyro {
+                timestamp: 1000,
+                ..
+            }
+        ));
+    }
+
+    fn key_events(glasses: &mut RokidAir) -> Vec<GlassesEvent> {
+        read_all(glasses)
+            .into_iter()
+            .filter(|event| event.kind() == crate::EventKind::KEY)
+            .collect()
+    }
+
+    #[test]
+    fn key_repeat() {
+        let (mut glasses, _) = glasses(&[
+            sensor_packet(1, 1000, GRAVITY),
+            misc_packet(1, 1),
+            sensor_packet(1, 6000, GRAVITY),
+            sensor_packet(1, 11000, GRAVITY),
+            misc_packet(0, 1),
+        ]);
+        glasses.set_key_repeat(Some(Duration::from_millis(10)));
+        assert!(matches!(
+            key_events(&mut glasses)[..],
+            [
+                GlassesEvent::KeyPress(0),
+                GlassesEvent::KeyRepeat(0),
+                GlassesEvent::KeyRelease(0),
+            ]
+        ));
+    }
+
+    #[test]
+    fn key_repeat_huge_interval() {
+        let (mut glasses, _) = glasses(&[
+            sensor_packet(1, 1000, GRAVITY),
+            misc_packet(1, 1),
+            sensor_packet(1, 2000, GRAVITY),
+        ]);
+        glasses.set_key_repeat(Some(Duration::MAX));
+        assert!(matches!(
+            key_events(&mut glasses)[..],
+            [GlassesEvent::KeyPress(0)]
+        ));
+    }
+
+    #[test]
+    fn proximity_state_waits_for_data() {
+        let (mut glasses, _) = glasses(&[
+            sensor_packet(3, 1000, Vector3::new(10.0, 20.0, 30.0)),
+            misc_packet(0, 0),
+        ]);
+        assert!(glasses.proximity_state().unwrap());
+        // The events read in the meantime are kept
+        assert!(matches!(
+            read_all(&mut glasses)[..],
+            [GlassesEvent::Magnetometer { .. }]
+        ));
+    }
+
+    #[test]
+    fn proximity_state_timeout() {
+        let (mut glasses, _) = glasses(&[]);
+        let start = Instant::now();
+        assert!(matches!(glasses.proximity_state(), Err(Error::Timeout)));
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn read_event_timeout_waits_for_pair() {
+        let (mut glasses, _) = glasses(&[
+            sensor_packet(1, 1000, GRAVITY),
+            sensor_packet(2, 1000, Vector3::zeros()),
+        ]);
+        assert!(matches!(
+            glasses.read_event_timeout(Duration::from_secs(1)),
+            Ok(Some(GlassesEvent::AccGyro {
+                timestamp: 1000,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            glasses.read_event_timeout(Duration::ZERO),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn accelerometer_only() {
+        let (mut glasses, _) = glasses(&[
+            sensor_packet(1, 1000, GRAVITY),
+            sensor_packet(1, 2000, GRAVITY),
+        ]);
+        let events = read_all(&mut glasses);
+        assert!(matches!(
+            events[..],
+            [GlassesEvent::Accelerometer { accelerometer, timestamp: 1000 }] if accelerometer == GRAVITY
+        ));
+    }
+
+    #[test]
+    fn gyroscope_only() {
+        let gyroscope = Vector3::new(0.1, 0.2, 0.3);
+        let bias = Vector3::new(0.1, 0.0, 0.0);
+        let (mut glasses, _) = glasses(&[
+            sensor_packet(2, 1000, gyroscope),
+            sensor_packet(2, 2000, gyroscope),
+        ]);
+        glasses.set_gyro_bias(bias);
+        let events = read_all(&mut glasses);
+        assert!(matches!(
+            events[..],
+            [GlassesEvent::Gyroscope { gyroscope: g, timestamp: 1000 }] if g == gyroscope - bias
+        ));
+    }
+
+    #[test]
+    fn out_of_tolerance_samples_are_not_paired() {
+        let gyroscope = Vector3::new(0.1, 0.2, 0.3);
+        let (mut glasses, _) = glasses(&[
+            sensor_packet(1, 1000, GRAVITY),
+            sensor_packet(2, 1010, gyroscope),
+            sensor_packet(1, 1012, GRAVITY),
+        ]);
+        glasses.set_acc_gyro_tolerance(Duration::from_micros(5));
+        let events = read_all(&mut glasses);
+        assert!(matches!(
+            events[..],
+            [
+                GlassesEvent::Accelerometer {
+                    timestamp: 1000,
+                    ..
+                },
+                GlassesEvent::AccGyro {
+                    timestamp: 1012,
+                    ..
+                },
+            ]
+        ));
+    }
+
+    #[test]
+    fn clock_reset() {
+        let (mut glasses, _) = glasses(&[
+            sensor_packet(1, 5_000_000, GRAVITY),
+            sensor_packet(2, 1000, Vector3::zeros()),
+        ]);
+        let events = read_all(&mut glasses);
+        assert!(matches!(
+            events[..],
+            [
+                GlassesEvent::Accelerometer {
+                    timestamp: 5_000_000,
+                    ..
+                },
+                GlassesEvent::ClockReset,
+            ]
+        ));
+        // The gyroscope sample after the reset is still waiting for its pair
+        assert!(glasses.last_gyroscope.is_some());
+    }
+
+    #[test]
+    fn model_from_product_string() {
+        assert_eq!(
+            RokidModel::from_product_string("Rokid Air"),
+            RokidModel::Air
+        );
+        assert_eq!(
+            RokidModel::from_product_string("Rokid Max "),
+            RokidModel::Max
+        );
+        assert_eq!(
+            RokidModel::from_product_string("Rokid Max V2.1"),
+            RokidModel::Max
+        );
+        assert_eq!(
+            RokidModel::from_product_string("Rokid Glasses"),
+            RokidModel::Unknown
+        );
+    }
+
+    #[test]
+    fn display_mode_round_trip() {
+        for mode in ALL_DISPLAY_MODES {
+            assert_eq!(
+                raw_to_display_mode(display_mode_to_raw(*mode)).unwrap(),
+                *mode
+            );
+        }
+        assert_eq!(
+            raw_to_display_mode(5).unwrap(),
+            DisplayMode::HighRefreshRate
+        );
+        assert!(raw_to_display_mode(6).is_err());
+    }
+
+    #[test]
+    fn read_only_display_mode() {
+        let (mut glasses, transport) = glasses(&[]);
+        transport.set_control_response(0x81, 0x0, 0x1, &[0, 5]);
+        assert_eq!(
+            glasses.get_display_mode().unwrap(),
+            DisplayMode::HighRefreshRate
+        );
+        assert_eq!(glasses.refresh_rate().unwrap(), 120);
+        assert_eq!(glasses.display_resolution().unwrap(), (1920, 1080));
+        glasses.ensure_display_mode(DisplayMode::Stereo).unwrap();
+        assert_eq!(transport.take_control_writes().len(), 1);
+    }
+
+    #[test]
+    fn set_display_mode_checks_model() {
+        let transport = Arc::new(MockTransport::new());
+        let mut glasses =
+            RokidAir::new_from_transport(Box::new(transport.clone()), RokidModel::Unknown);
+        assert!(glasses
+            .set_display_mode(DisplayMode::HighRefreshRate)
+            .is_err());
+        assert!(transport.take_control_writes().is_empty());
+        glasses.set_display_mode(DisplayMode::Stereo).unwrap();
+        assert_eq!(transport.take_control_writes().len(), 1);
+    }
+
+    #[test]
+    fn set_display_mode_accepts_supported() {
+        let (mut glasses, transport) = glasses(&[]);
+        for mode in glasses.supported_display_modes() {
+            glasses.set_display_mode(*mode).unwrap();
+        }
+        assert_eq!(
+            transport.take_control_writes().len(),
+            ALL_DISPLAY_MODES.len()
+        );
+    }
+
+    #[test]
+    fn ensure_display_mode_matching() {
+        let (mut glasses, transport) = glasses(&[]);
+        transport.set_control_response(0x81, 0x0, 0x1, &[0, 1]);
+        glasses.ensure_display_mode(DisplayMode::Stereo).unwrap();
+        assert!(transport.take_control_writes().is_empty());
+    }
+
+    #[test]
+    fn ensure_display_mode_mismatch() {
+        let (mut glasses, transport) = glasses(&[]);
+        transport.set_control_response(0x81, 0x0, 0x1, &[0, 0]);
+        glasses.ensure_display_mode(DisplayMode::Stereo).unwrap();
+        let writes = transport.take_control_writes();
+        assert_eq!(writes.len(), 1);
+        assert_eq!(
+            (writes[0].request, writes[0].value, writes[0].index),
+            (0x1, 1, 0x1)
+        );
+    }
+
+    #[test]
+    fn set_display_mode_verified_no_extra_wait() {
+        let (mut glasses, transport) = glasses(&[]);
+        // The glasses never switch
+        transport.set_control_response(0x81, 0x0, 0x1, &[0, 0]);
+        let start = Instant::now();
+        for attempts in [0, 1] {
+            assert!(matches!(
+                glasses.set_display_mode_verified(
+                    DisplayMode::Stereo,
+                    Duration::from_secs(10),
+                    attempts
+                ),
+                Err(Error::Other(_))
+            ));
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+        transport.set_control_response(0x81, 0x0, 0x1, &[0, 1]);
+        glasses
+            .set_display_mode_verified(DisplayMode::Stereo, Duration::from_secs(10), 0)
+            .unwrap();
+    }
+
+    fn acc_gyro_packets(
+        accelerometer: impl Fn(usize) -> Vector3<f32>,
+        gyroscope: Vector3<f32>,
+        count: usize,
+    ) -> Vec<Vec<u8>> {
+        (0..count)
+            .map(|i| {
+                combined_packet(
+                    i as u64 * 1_000_000,
+                    accelerometer(i),
+                    gyroscope,
+                    Vector3::zeros(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn gyro_bias_still() {
+        let bias = Vector3::new(0.01, -0.02, 0.03);
+        let count = 100_000;
+        let (mut glasses, _) = glasses(&acc_gyro_packets(
+            |_| Vector3::new(0.3, STANDARD_GRAVITY, -0.2),
+            bias,
+            count,
+        ));
+        let estimate = glasses.estimate_gyro_bias(count).unwrap();
+        assert!((estimate - bias).norm() < 1e-6);
+    }
+
+    #[test]
+    fn gyro_bias_moving() {
+        let (mut glasses, _) = glasses(&acc_gyro_packets(
+            |i| Vector3::new(if i % 2 == 0 { 1.0 } else { -1.0 }, STANDARD_GRAVITY, 0.0),
+            Vector3::zeros(),
+            1000,
+        ));
+        assert!(glasses.estimate_gyro_bias(1000).is_err());
+    }
+
+    #[test]
+    fn set_volume_range() {
+        let (mut glasses, transport) = glasses(&[]);
+        assert!(glasses.set_volume(11).is_err());
+        assert!(transport.take_control_writes().is_empty());
+        glasses.set_volume(10).unwrap();
+        let writes = transport.take_control_writes();
+        assert_eq!(writes.len(), 1);
+        assert_eq!(
+            (writes[0].request, writes[0].value, writes[0].index),
+            (0x1, 100, 0xa)
+        );
+    }
+
+    #[test]
+    fn set_brightness_percent_range() {
+        let (mut glasses, transport) = glasses(&[]);
+        assert!(glasses.set_brightness_percent(0).is_err());
+        assert!(glasses.set_brightness_percent(101).is_err());
+        assert!(transport.take_control_writes().is_empty());
+        glasses.set_brightness_percent(1).unwrap();
+        glasses.set_brightness_percent(100).unwrap();
+        let writes: Vec<_> = transport
+            .take_control_writes()
+            .iter()
+            .map(|write| (write.request, write.value, write.index))
+            .collect();
+        assert_eq!(writes, [(0x2, 1, 0x2), (0x2, 100, 0x2)]);
+    }
+
+    #[test]
+    fn volume_round_trip() {
+        let (mut glasses, transport) = glasses(&[]);
+        glasses.set_volume(7).unwrap();
+        let written = transport.take_control_writes()[0].value as u8;
+        transport.set_control_response(0x81, 0x0, 0xa, &[0, written]);
+        assert_eq!(glasses.volume().unwrap(), 7);
+        transport.set_control_response(0x81, 0x0, 0xa, &[0, 75]);
+        assert!(glasses.volume().is_err());
+        assert_eq!(glasses.get_volume_level().unwrap(), 7);
+    }
+
+    #[test]
+    fn proximity_debounce() {
+        // Near, then flickering, then far for good
+        let (mut glasses, _) = glasses(&[
+            misc_packet(0, 0),
+            misc_packet(0, 1),
+            misc_packet(0, 0),
+            misc_packet(0, 1),
+            misc_packet(0, 1),
+            misc_packet(0, 0),
+            misc_packet(0, 1),
+            misc_packet(0, 1),
+            misc_packet(0, 1),
+        ]);
+        glasses.set_proximity_debounce(3);
+        assert!(matches!(
+            read_all(&mut glasses)[..],
+            [GlassesEvent::ProximityFar]
+        ));
+    }
+
+    #[test]
+    fn volume_changed() {
+        let packets: Vec<_> = [30, 30, 40, 40]
+            .into_iter()
+            .enumerate()
+            .map(|(i, volume)| {
+                let mut packet = combined_packet(
+                    i as u64 * 1_000_000,
+                    GRAVITY,
+                    Vector3::zeros(),
+                    Vector3::zeros(),
+                );
+                // Volume byte of the combined packet
+                packet[60] = volume;
+                packet
+            })
+            .collect();
+        let (mut glasses, _) = glasses(&packets);
+        let events: Vec<_> = read_all(&mut glasses)
+            .into_iter()
+            .filter(|event| event.kind() == crate::EventKind::OTHER)
+            .collect();
+        assert!(matches!(events[..], [GlassesEvent::VolumeChanged(40)]));
+        assert_eq!(glasses.last_reported_volume(), Some(40));
+    }
+
+    #[test]
+    fn alt_pid_endpoint() {
+        assert_eq!(interrupt_endpoint(RokidAir::PID), 0x82);
+        assert_eq!(interrupt_endpoint(RokidAir::ALT_PID), 0x83);
+
+        let transport = Arc::new(MockTransport::new());
+        transport.push_interrupt_packet(INTERRUPT_IN_ENDPOINT, &misc_packet(1, 1));
+        transport.push_interrupt_packet(INTERRUPT_IN_ENDPOINT_ALT, &misc_packet(2, 1));
+        let mut glasses = RokidAir::new_from_parts(
+            transport,
+            RokidModel::Air,
+            interrupt_endpoint(RokidAir::ALT_PID),
+        );
+        assert!(matches!(
+            read_all(&mut glasses)[..],
+            [GlassesEvent::KeyPress(1)]
+        ));
+    }
+
+    #[test]
+    fn reset_through_arc() {
+        let transport = Arc::new(MockTransport::new());
+        let mut glasses = RokidAir::new_from_transport(Box::new(transport), RokidModel::Air);
+        glasses.reset().unwrap();
+        assert!(matches!(
+            read_all(&mut glasses)[..],
+            [GlassesEvent::ClockReset]
+        ));
+    }
+
+    #[test]
+    fn reset_shared_transport() {
+        // The test keeps a reference to the transport, so it can't be reset
+        let (mut glasses, _transport) = glasses(&[]);
+        assert!(matches!(glasses.reset(), Err(Error::Other(_))));
+        assert!(read_all(&mut glasses).is_empty());
+    }
+
+    #[test]
+    fn short_packets_are_dropped() {
+        let mut packet = sensor_packet(1, 1000, GRAVITY);
+        packet.truncate(20);
+        let (mut glasses, _) = glasses(&[packet]);
+        assert!(read_all(&mut glasses).is_empty());
+    }
+}
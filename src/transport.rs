@@ -0,0 +1,377 @@
+// Copyright (C) 2023, Alex Badics
+// This file is part of ar-drivers-rs
+// Licensed under the MIT license. See LICENSE file in the project root for details.
+
+//! Abstraction over the low level communication with the glasses. See [`Transport`]
+//!
+//! Drivers that use it can be run on top of other backends than [`rusb`], e.g. a mock
+//! for testing.
+
+#[cfg(any(test, feature = "mock"))]
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+use std::{sync::Arc, time::Duration};
+
+#[cfg(feature = "rusb")]
+use rusb::{request_type, DeviceHandle, GlobalContext};
+
+use crate::{Error, Result};
+
+/// Vendor specific control transfers and interrupt reads, as used by most glasses
+pub trait Transport: Send + Sync {
+    /// Vendor specific control transfer from the device. Returns the number of bytes read.
+    fn read_control(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize>;
+
+    /// Vendor specific control transfer to the device. Returns the number of bytes written.
+    fn write_control(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<usize>;
+
+    /// Read from an interrupt endpoint. Returns the number of bytes read.
+    fn read_interrupt(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize>;
+
+    /// Reset the device, and restore the state needed for communication (e.g. claimed
+    /// interfaces). Returns [`Error::Disconnected`] if the device has to be reopened.
+    fn reset(&mut self) -> Result<()> {
+        Err(Error::NotImplemented)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn read_control(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        self.as_ref()
+            .read_control(request, value, index, buf, timeout)
+    }
+
+    fn write_control(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        self.as_ref()
+            .write_control(request, value, index, buf, timeout)
+    }
+
+    fn read_interrupt(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        self.as_ref().read_interrupt(endpoint, buf, timeout)
+    }
+
+    /// Only works if this is the last reference to the transport
+    fn reset(&mut self) -> Result<()> {
+        Arc::get_mut(self)
+            .ok_or(Error::Other("The transport is shared, it can't be reset"))?
+            .reset()
+    }
+}
+
+/// A [`Transport`] that returns scripted responses, for testing drivers and applications
+/// without hardware.
+///
+/// Wrap it in an [`Arc`] to keep access to it after handing it over to a driver.
+#[cfg(any(test, feature = "mock"))]
+#[derive(Default)]
+pub struct MockTransport {
+    state: Mutex<MockState>,
+}
+
+#[cfg(any(test, feature = "mock"))]
+#[derive(Default)]
+struct MockState {
+    control_responses: HashMap<(u8, u16, u16), Vec<u8>>,
+    interrupt_packets: VecDeque<(u8, Vec<u8>)>,
+    written: Vec<MockControlWrite>,
+}
+
+/// A control write recorded by [`MockTransport`]
+#[cfg(any(test, feature = "mock"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockControlWrite {
+    /// Request number
+    pub request: u8,
+    /// Value field of the request
+    pub value: u16,
+    /// Index field of the request
+    pub index: u16,
+    /// The data sent
+    pub data: Vec<u8>,
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl MockTransport {
+    /// Create a mock without any scripted responses
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Respond with `response` to every control read with the given parameters.
+    /// Unscripted control reads fail with [`Error::Other`].
+    pub fn set_control_response(&self, request: u8, value: u16, index: u16, response: &[u8]) {
+        self.state
+            .lock()
+            .unwrap()
+            .control_responses
+            .insert((request, value, index), response.to_vec());
+    }
+
+    /// Queue a packet to be returned by an interrupt read on `endpoint`. Packets are
+    /// returned in order; reads fail with [`Error::Timeout`] if the queue is empty.
+    pub fn push_interrupt_packet(&self, endpoint: u8, packet: &[u8]) {
+        self.state
+            .lock()
+            .unwrap()
+            .interrupt_packets
+            .push_back((endpoint, packet.to_vec()));
+    }
+
+    /// Return (and forget) the control writes received so far
+    pub fn take_control_writes(&self) -> Vec<MockControlWrite> {
+        std::mem::take(&mut self.state.lock().unwrap().written)
+    }
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl Transport for MockTransport {
+    fn read_control(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        _timeout: Duration,
+    ) -> Result<usize> {
+        let state = self.state.lock().unwrap();
+        let response = state
+            .control_responses
+            .get(&(request, value, index))
+            .ok_or(Error::Other(
+                "No scripted response for this control request",
+            ))?;
+        let length = response.len().min(buf.len());
+        buf[..length].copy_from_slice(&response[..length]);
+        Ok(length)
+    }
+
+    fn write_control(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        _timeout: Duration,
+    ) -> Result<usize> {
+        self.state.lock().unwrap().written.push(MockControlWrite {
+            request,
+            value,
+            index,
+            data: buf.to_vec(),
+        });
+        Ok(buf.len())
+    }
+
+    fn read_interrupt(&self, endpoint: u8, buf: &mut [u8], _timeout: Duration) -> Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        let Some(position) = state
+            .interrupt_packets
+            .iter()
+            .position(|(packet_endpoint, _)| *packet_endpoint == endpoint)
+        else {
+            return Err(Error::Timeout);
+        };
+        let (_, packet) = state.interrupt_packets.remove(position).unwrap();
+        let length = packet.len().min(buf.len());
+        buf[..length].copy_from_slice(&packet[..length]);
+        Ok(length)
+    }
+
+    /// Always succeeds, scripted responses and queued packets are kept
+    fn reset(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rusb")]
+impl Transport for DeviceHandle<GlobalContext> {
+    fn read_control(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        let result = DeviceHandle::read_control(
+            self,
+            request_type(
+                rusb::Direction::In,
+                rusb::RequestType::Vendor,
+                rusb::Recipient::Device,
+            ),
+            request,
+            value,
+            index,
+            buf,
+            timeout,
+        )?;
+        trace!(
+            "read_control request={request:#x} value={value:#x} index={index:#x}: {result} bytes"
+        );
+        Ok(result)
+    }
+
+    fn write_control(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        let result = DeviceHandle::write_control(
+            self,
+            request_type(
+                rusb::Direction::Out,
+                rusb::RequestType::Vendor,
+                rusb::Recipient::Device,
+            ),
+            request,
+            value,
+            index,
+            buf,
+            timeout,
+        )?;
+        trace!(
+            "write_control request={request:#x} value={value:#x} index={index:#x}: {result} bytes"
+        );
+        Ok(result)
+    }
+
+    fn read_interrupt(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        let result = DeviceHandle::read_interrupt(self, endpoint, buf, timeout)?;
+        trace!("read_interrupt endpoint={endpoint:#x}: {result} bytes");
+        Ok(result)
+    }
+}
+
+/// A USB device with one of its interfaces claimed. The interface is released on drop.
+#[cfg(feature = "rusb")]
+pub struct UsbTransport {
+    device_handle: DeviceHandle<GlobalContext>,
+    interface: u8,
+}
+
+#[cfg(feature = "rusb")]
+impl UsbTransport {
+    /// Claim `interface` of the device (detaching the kernel driver if needed)
+    pub fn new(device_handle: DeviceHandle<GlobalContext>, interface: u8) -> Result<Self> {
+        device_handle.set_auto_detach_kernel_driver(true)?;
+        device_handle.claim_interface(interface)?;
+        Ok(Self {
+            device_handle,
+            interface,
+        })
+    }
+
+    /// The underlying device handle
+    pub fn device_handle(&self) -> &DeviceHandle<GlobalContext> {
+        &self.device_handle
+    }
+
+    /// Where and how the device is connected. See [`ConnectionInfo`]
+    pub fn connection_info(&self) -> ConnectionInfo {
+        let device = self.device_handle.device();
+        ConnectionInfo {
+            bus_number: device.bus_number(),
+            address: device.address(),
+            speed: device.speed(),
+            interface: self.interface,
+        }
+    }
+}
+
+/// Diagnostic information about a USB connection. Returned by
+/// [`UsbTransport::connection_info`]
+#[cfg(feature = "rusb")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// Number of the USB bus the device is connected to
+    pub bus_number: u8,
+    /// Address of the device on the USB bus
+    pub address: u8,
+    /// Negotiated speed of the connection. A slower speed than expected (e.g. because of a
+    /// bad hub or cable) can cause dropped sensor packets.
+    pub speed: rusb::Speed,
+    /// Number of the claimed interface
+    pub interface: u8,
+}
+
+#[cfg(feature = "rusb")]
+impl Drop for UsbTransport {
+    fn drop(&mut self) {
+        // Errors are ignored: the device may have already been unplugged
+        let _ = self.device_handle.release_interface(self.interface);
+    }
+}
+
+#[cfg(feature = "rusb")]
+impl Transport for UsbTransport {
+    fn reset(&mut self) -> Result<()> {
+        match self.device_handle.reset() {
+            Ok(()) => (),
+            // The device re-enumerated, e.g. with a different address
+            Err(rusb::Error::NotFound) => return Err(Error::Disconnected),
+            Err(e) => return Err(e.into()),
+        }
+        self.device_handle.claim_interface(self.interface)?;
+        Ok(())
+    }
+
+    fn read_control(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        Transport::read_control(&self.device_handle, request, value, index, buf, timeout)
+    }
+
+    fn write_control(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        Transport::write_control(&self.device_handle, request, value, index, buf, timeout)
+    }
+
+    fn read_interrupt(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        Transport::read_interrupt(&self.device_handle, endpoint, buf, timeout)
+    }
+}
@@ -12,16 +12,52 @@ use crate::{Error, Result};
 #[cfg(feature = "rusb")]
 #[cfg(not(target_os = "android"))]
 pub fn get_device_vid_pid(vid: u16, pid: u16) -> Result<Device<GlobalContext>> {
+    get_devices_vid_pid(vid, pid)?
+        .into_iter()
+        .next()
+        .ok_or(Error::NotFound)
+}
+
+#[cfg(feature = "rusb")]
+#[cfg(not(target_os = "android"))]
+pub fn get_devices_vid_pid(vid: u16, pid: u16) -> Result<Vec<Device<GlobalContext>>> {
+    let mut result = Vec::new();
     for device in DeviceList::new()?.iter() {
         if let Ok(desc) = device.device_descriptor() {
             if desc.vendor_id() == vid && desc.product_id() == pid {
-                return Ok(device);
+                result.push(device);
             }
         }
     }
+    Ok(result)
+}
+
+#[cfg(feature = "rokid")]
+#[cfg(not(target_os = "android"))]
+pub fn get_device_bus_address(bus_number: u8, address: u8) -> Result<Device<GlobalContext>> {
+    for device in DeviceList::new()?.iter() {
+        if device.bus_number() == bus_number && device.address() == address {
+            return Ok(device);
+        }
+    }
     Err(Error::NotFound)
 }
 
+/// Open the device. Permission problems are reported as [`Error::PermissionDenied`],
+/// which explains how to fix them.
+#[cfg(feature = "rusb")]
+pub fn open_device(device: &Device<GlobalContext>) -> Result<DeviceHandle<GlobalContext>> {
+    device
+        .open()
+        .map_err(|e| match (e, device.device_descriptor()) {
+            (rusb::Error::Access, Ok(desc)) => Error::PermissionDenied {
+                vendor_id: desc.vendor_id(),
+                product_id: desc.product_id(),
+            },
+            (e, _) => e.into(),
+        })
+}
+
 #[cfg(feature = "rusb")]
 pub fn get_interface_for_endpoint(
     device: &Device<GlobalContext>,